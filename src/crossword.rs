@@ -1,15 +1,46 @@
 extern crate priority_queue;
 extern crate rand;
 
+mod anytime;
+mod best_first;
+mod checkpoint;
+mod estimate;
+mod failure;
+mod heuristics;
+mod limits;
+mod local;
+mod parallel;
+mod progress;
+mod refill;
+mod restarts;
+mod stats;
+
+use self::parallel::Split;
+use crate::skip_iter::{and, diff, filter_, leaf};
 use ncurses::*;
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::collections::hash_set::HashSet;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tui::View;
 use words::dictionary::{english_scrabble_dict, Dictionary};
 use words::LetterSet;
 
+pub use self::anytime::Closeness;
+pub use self::best_first::{letter_score, Objective};
+pub use self::checkpoint::SearchState;
+pub use self::estimate::Estimate;
+pub use self::failure::{Entry, Failure};
+pub use self::heuristics::{Strategy, Timing, ValueOrder, VariableOrder};
+pub use self::limits::{CancelToken, Limit, SolveOptions};
+pub use self::progress::{Observer, Progress, Step, StepKind};
+pub use self::restarts::Restarts;
+pub use self::stats::{SolveStats, Trace};
+
 #[derive(Clone, Debug, Default)]
 struct Line {
   direction: u8,
@@ -22,18 +53,234 @@ impl Line {
   }
 }
 
+/// The dictionary's words of one length, identified by their order in the
+/// dictionary, with postings lists and bitset masks of the ids of the words
+/// having each letter at each position.
+#[derive(Debug)]
+struct WordIndex {
+  length: usize,
+  ords: Vec<u8>,
+  // Sorted ids, indexed by position and then letter ord.
+  postings: Vec<Vec<Vec<u32>>>,
+  // The same sets as bitsets over every id, filled in by `index_bits`.
+  masks: Vec<Vec<Vec<u64>>>,
+}
+
+impl WordIndex {
+  fn new(length: usize) -> WordIndex {
+    WordIndex {
+      length,
+      ords: vec![],
+      postings: vec![vec![]; length],
+      masks: vec![],
+    }
+  }
+
+  fn word_count(&self) -> usize {
+    self.ords.len() / self.length
+  }
+
+  fn word(&self, id: u32) -> &[u8] {
+    let b = id as usize * self.length;
+    &self.ords[b..b + self.length]
+  }
+
+  fn postings(&self, pos: u8, ord: u8) -> &[u32] {
+    match self.postings[pos as usize].get(ord as usize) {
+      Some(ids) => ids,
+      None => &[],
+    }
+  }
+
+  /// The id of a word, looked up in its shortest postings list.
+  fn id(&self, ords: &[u8]) -> Option<u32> {
+    let lists = ords.iter().enumerate();
+    let ids = lists.map(|(pos, o)| self.postings(pos as u8, *o));
+    let shortest = ids.min_by_key(|ids| ids.len())?;
+    shortest.iter().copied().find(|id| self.word(*id) == ords)
+  }
+
+  fn add(&mut self, w: &str) {
+    let id = self.word_count() as u32;
+    for (c, postings) in w.chars().zip(self.postings.iter_mut()) {
+      let o = LetterSet::index(c).unwrap();
+      if postings.len() <= o as usize {
+        postings.resize(o as usize + 1, vec![]);
+      }
+      postings[o as usize].push(id);
+      self.ords.push(o);
+    }
+    assert_eq!(self.ords.len(), (id as usize + 1) * self.length);
+  }
+
+  /// Builds the bitset masks once every word has been added.
+  fn index_bits(&mut self) {
+    let chunks = self.word_count().div_ceil(64);
+    self.masks = self
+      .postings
+      .iter()
+      .map(|letters| {
+        letters
+          .iter()
+          .map(|ids| {
+            let mut mask = vec![0u64; chunks];
+            for id in ids {
+              mask[*id as usize / 64] |= 1 << (id % 64);
+            }
+            mask
+          })
+          .collect()
+      })
+      .collect();
+  }
+
+  fn all_bits(&self) -> Vec<u64> {
+    let mut bits = vec![0u64; self.word_count().div_ceil(64)];
+    for id in 0..self.word_count() {
+      bits[id / 64] |= 1 << (id % 64);
+    }
+    bits
+  }
+}
+
+/// The ids of the remaining words in a line: a bitset over every id of the
+/// line's length while many remain, and a sorted list once few do.
+#[derive(Clone, Debug)]
+enum Candidates {
+  Bits(Vec<u64>),
+  Ids(Vec<u32>),
+}
+
+fn ord_char(ord: u8) -> char {
+  let mut set = LetterSet::default();
+  set.insert_index(ord);
+  let ch = set.chars().next().unwrap();
+  ch
+}
+
+fn bit_ids(bits: &[u64]) -> impl Iterator<Item = u32> + '_ {
+  bits.iter().enumerate().flat_map(|(i, chunk)| {
+    let mut chunk = *chunk;
+    std::iter::from_fn(move || {
+      if chunk == 0 {
+        None
+      } else {
+        let bit = chunk.trailing_zeros();
+        chunk &= chunk - 1;
+        Some(i as u32 * 64 + bit)
+      }
+    })
+  })
+}
+
+/// A set of search levels, as a bitset. Level 0 is the root, before any
+/// letter has been chosen.
 #[derive(Clone, Debug, Default)]
+struct Levels(Vec<u64>);
+
+impl Levels {
+  fn insert(&mut self, level: usize) {
+    if self.0.len() <= level / 64 {
+      self.0.resize(level / 64 + 1, 0);
+    }
+    self.0[level / 64] |= 1 << (level % 64);
+  }
+
+  fn remove(&mut self, level: usize) {
+    if let Some(chunk) = self.0.get_mut(level / 64) {
+      *chunk &= !(1 << (level % 64));
+    }
+  }
+
+  fn contains(&self, level: usize) -> bool {
+    match self.0.get(level / 64) {
+      Some(chunk) => chunk & (1 << (level % 64)) != 0,
+      None => false,
+    }
+  }
+
+  fn union(&mut self, other: &Levels) {
+    if self.0.len() < other.0.len() {
+      self.0.resize(other.0.len(), 0);
+    }
+    for (a, b) in self.0.iter_mut().zip(&other.0) {
+      *a |= b;
+    }
+  }
+
+  fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+    bit_ids(&self.0).map(|level| level as usize)
+  }
+}
+
+#[derive(Clone, Debug)]
 struct LineState {
   // Length for this set is implied by length of position_letters.
   position_letters: Vec<LetterSet>,
-  ords: Vec<u8>,
+  // Histogram of letter ords at each position, over the remaining words.
+  position_counts: Vec<Vec<u32>>,
+  words: Arc<WordIndex>,
+  candidates: Candidates,
+  count: usize,
+  // The levels whose choices narrowed this line.
+  reasons: Levels,
 }
 
 impl LineState {
-  fn new(length: usize) -> LineState {
+  fn new(words: Arc<WordIndex>, candidates: Candidates) -> LineState {
+    let mut position_counts: Vec<Vec<u32>> = words
+      .postings
+      .iter()
+      .map(|postings| vec![0; postings.len()])
+      .collect();
+    match &candidates {
+      Candidates::Bits(bits) => {
+        for (counts, masks) in position_counts.iter_mut().zip(&words.masks) {
+          for (count, mask) in counts.iter_mut().zip(masks) {
+            *count = bits
+              .iter()
+              .zip(mask)
+              .map(|(b, m)| (b & m).count_ones())
+              .sum();
+          }
+        }
+      }
+      Candidates::Ids(ids) => {
+        for id in ids {
+          for (o, counts) in words.word(*id).iter().zip(position_counts.iter_mut()) {
+            counts[*o as usize] += 1;
+          }
+        }
+      }
+    }
+    let position_letters = position_counts
+      .iter()
+      .map(|counts| {
+        let mut set = LetterSet::default();
+        for (o, count) in counts.iter().enumerate() {
+          if *count > 0 {
+            set.insert_index(o as u8);
+          }
+        }
+        set
+      })
+      .collect();
+    let count = position_counts[0].iter().sum::<u32>() as usize;
+    // An id list is smaller than the bitset once fewer than one in 32 ids
+    // remain.
+    let candidates = match candidates {
+      Candidates::Bits(bits) if count * 32 < bits.len() * 64 => {
+        Candidates::Ids(bit_ids(&bits).collect())
+      }
+      candidates => candidates,
+    };
     LineState {
-      position_letters: vec![Default::default(); length],
-      ords: vec![],
+      position_letters,
+      position_counts,
+      words,
+      candidates,
+      count,
+      reasons: Levels::default(),
     }
   }
 
@@ -41,49 +288,74 @@ impl LineState {
     self.position_letters.len()
   }
 
-  fn word(&self, w: usize) -> &[u8] {
-    let n = self.length();
-    let b = w * n;
-    let e = b + n;
-    &self.ords[b..e]
+  fn word_count(&self) -> usize {
+    self.count
   }
 
-  fn word_count(&self) -> usize {
-    self.ords.len() / self.length()
+  fn letter_count(&self, pos: u8, ord: u8) -> usize {
+    let counts = &self.position_counts[pos as usize];
+    counts.get(ord as usize).cloned().unwrap_or(0) as usize
   }
 
-  fn add(&mut self, w: &str) {
-    self.ords.len();
-    let new_len = self.ords.len() + self.length();
-    self.ords.reserve(self.length());
-    let (ords, sets) = (&mut self.ords, &mut self.position_letters);
-    for (o, set) in w.chars().map(LetterSet::index).zip(sets.iter_mut()) {
-      let o = o.unwrap();
-      set.insert_index(o);
-      ords.push(o);
+  fn contains(&self, id: u32) -> bool {
+    match &self.candidates {
+      Candidates::Bits(bits) => bits[id as usize / 64] & (1 << (id % 64)) != 0,
+      Candidates::Ids(ids) => ids.binary_search(&id).is_ok(),
+    }
+  }
+
+  fn ids(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+    match &self.candidates {
+      Candidates::Bits(bits) => Box::new(bit_ids(bits)),
+      Candidates::Ids(ids) => Box::new(ids.iter().cloned()),
     }
-    assert_eq!(new_len, self.ords.len());
   }
 
-  fn add_ords(&mut self, w: &[u8]) {
-    self.ords.reserve(w.len());
-    let (ords, sets) = (&mut self.ords, &mut self.position_letters);
-    for (o, set) in w.iter().zip(sets.iter_mut()) {
-      let o = *o;
-      set.insert_index(o);
-      ords.push(o);
+  fn first(&self) -> Option<u32> {
+    match &self.candidates {
+      Candidates::Bits(bits) => bit_ids(bits).next(),
+      Candidates::Ids(ids) => ids.first().cloned(),
     }
   }
 
-  fn with_chosen(&self, ord: u8, pos: u8) -> LineState {
-    let mut ret = LineState::new(self.length());
-    for i in 0..self.word_count() {
-      let word = self.word(i);
-      if word[pos as usize] == ord {
-        ret.add_ords(word);
+  /// The candidates left after restricting a position to a set of letters,
+  /// if given, and removing the `used` ids.
+  fn narrowed(&self, restrict: Option<(u8, &LetterSet)>, used: &[u32]) -> Candidates {
+    match &self.candidates {
+      Candidates::Bits(bits) => {
+        let mut bits = bits.clone();
+        if let Some((pos, letters)) = restrict {
+          let mut allowed = vec![0u64; bits.len()];
+          for o in letters.indices() {
+            if let Some(mask) = self.words.masks[pos as usize].get(o as usize) {
+              for (a, m) in allowed.iter_mut().zip(mask) {
+                *a |= m;
+              }
+            }
+          }
+          for (b, a) in bits.iter_mut().zip(&allowed) {
+            *b &= a;
+          }
+        }
+        for id in used {
+          bits[*id as usize / 64] &= !(1 << (id % 64));
+        }
+        Candidates::Bits(bits)
       }
+      Candidates::Ids(ids) => Candidates::Ids(match restrict {
+        Some((pos, letters)) if letters.len() == 1 => {
+          let o = letters.indices().next().unwrap();
+          let postings = self.words.postings(pos, o);
+          diff(and(leaf(ids), leaf(postings)), leaf(used)).collect()
+        }
+        Some((pos, letters)) => {
+          let words = &self.words;
+          let keep = |id: u32| letters.contains_index(words.word(id)[pos as usize]);
+          diff(filter_(leaf(ids), keep), leaf(used)).collect()
+        }
+        None => diff(leaf(ids), leaf(used)).collect(),
+      }),
     }
-    ret
   }
 }
 
@@ -102,7 +374,9 @@ impl Puzzle {
   fn new(grid: &Grid) -> Puzzle {
     let cell_count = grid.squares.len();
     let cell_indices = 0..grid.squares.len();
-    let cell_positions: Vec<_> = grid.squares.keys().cloned().collect();
+    // Reading order, so that a seeded solve visits cells deterministically.
+    let mut cell_positions: Vec<_> = grid.squares.keys().cloned().collect();
+    cell_positions.sort_by_key(|&(x, y)| (y, x));
     let loc_to_ci: HashMap<_, _> = cell_positions
       .iter()
       .cloned()
@@ -149,41 +423,217 @@ impl Puzzle {
   }
 }
 
+// How long the TUI lets a depth-first solve run before pausing it.
+const SOLVE_TIME: Duration = Duration::from_secs(60);
+
+// How long the TUI's benchmark gives each solve, and how many seeds it
+// solves each strategy from.
+const BENCHMARK_TIME: Duration = Duration::from_secs(2);
+const BENCHMARK_SEEDS: usize = 3;
+
+// How often the TUI's benchmark checks for Escape while it solves.
+const KEY_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+// Word swaps allowed per local search.
+const LOCAL_STEPS: usize = 100000;
+
+// Probes for the ETA shown while solving in the TUI.
+const ETA_PROBES: usize = 100;
+
+// Where the TUI saves a search which was paused.
+const CHECKPOINT: &str = "crossword.checkpoint";
+
+// Where the TUI traces solves to, when tracing is on.
+const TRACE: &str = "crossword.trace";
+
+// Choices in the longest nogood a search learns, and how many it keeps.
+const MAX_NOGOOD_LEN: usize = 16;
+const MAX_NOGOODS: usize = 10000;
+
+/// The state of one depth-first solve which lives outside the solver's trail.
+struct Search<'a, R: Rng> {
+  strategy: Strategy,
+  options: SolveOptions,
+  rng: &'a mut R,
+  observer: &'a mut dyn Observer,
+  nodes: usize,
+  backtracks: usize,
+  // The node count `options.max_nodes` counts from, and the limit which
+  // stopped the search, if one has.
+  first_node: usize,
+  stopped: Option<Limit>,
+  // The node count at which to give up, for restarts, and a token which
+  // stops the threads of a parallel solve. Neither counts as a limit.
+  max_nodes: usize,
+  stop: Option<CancelToken>,
+  // The settled squares of the closest node to a fill so far, by
+  // `options.keep_closest`, and how close it came.
+  closest: Option<(usize, Vec<(usize, char)>)>,
+  // Where to hand off subtrees in a parallel solve.
+  split: Option<Split<'a>>,
+  // When the search estimated its ETA, and what it came to.
+  eta: Option<(Instant, Duration)>,
+  // Whether to jump back over choices which played no part in a conflict,
+  // and learn nogoods from it, rather than backtrack chronologically.
+  backjumping: bool,
+  stats: SolveStats,
+}
+
+impl<'a, R: Rng> Search<'a, R> {
+  fn new(
+    strategy: Strategy,
+    options: &SolveOptions,
+    rng: &'a mut R,
+    observer: &'a mut dyn Observer,
+  ) -> Search<'a, R> {
+    Search {
+      strategy,
+      options: options.clone(),
+      rng,
+      observer,
+      nodes: 0,
+      backtracks: 0,
+      first_node: 0,
+      stopped: None,
+      max_nodes: usize::MAX,
+      stop: None,
+      closest: None,
+      split: None,
+      eta: None,
+      backjumping: true,
+      stats: SolveStats::default(),
+    }
+  }
+}
+
 enum SolveResult {
   None,
   Incomplete(Vec<(usize, char)>),
   Solution(Vec<(usize, char)>),
 }
 
+/// Something a search can try: a letter for a cell, or a word for a line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Choice {
+  Letter(usize, u8),
+  Word(usize, u32),
+}
+
+/// What a depth-first solve branches on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Branching {
+  // Each letter of the cell with the fewest letters.
+  Letter,
+  // Each word of the line with the fewest words, committed whole.
+  Word,
+}
+
+/// One level of a depth-first solve: the choice being tried with the trail
+/// mark from before it, the choices left to try next (last first), and the
+/// levels blamed for the choices which have failed.
+#[derive(Clone, Debug)]
+struct Frame {
+  chosen: Option<(Choice, usize)>,
+  untried: Vec<Choice>,
+  conflicts: Levels,
+}
+
+/// How a depth-first solve of a grid ended.
+pub enum Outcome {
+  Solved,
+  Failed(Failure),
+  // A limit stopped the search before it finished.
+  Paused(SearchState, Limit),
+  // A limit stopped a search which can't be resumed, like a parallel one.
+  Stopped(Limit),
+}
+
+/// What a search should branch on next, if anything.
+enum Branch<T> {
+  DeadEnd,
+  Complete,
+  At(T),
+}
+
+/// A change to a `Solver`, recorded on its trail so backtracking can undo it.
 #[derive(Clone)]
-struct Solver<'a> {
-  puzzle: &'a Puzzle,
-  line_states: Vec<Rc<LineState>>,
+enum Undo {
+  // A line narrowed from this state.
+  Line(usize, LineState),
+  // A line committed the word of this length and id.
+  Commit((usize, u32)),
 }
 
-impl<'a> Solver<'a> {
-  fn new(puzzle: &'a Puzzle, dictionary: &Dictionary) -> Solver<'a> {
+#[derive(Clone)]
+struct Solver {
+  puzzle: Arc<Puzzle>,
+  line_states: Vec<LineState>,
+  // Words which lines have settled to, by length and id, and the line which
+  // settled to each.
+  committed: HashMap<(usize, u32), usize>,
+  // Sorted ids of the committed words of each length.
+  used: HashMap<usize, Vec<u32>>,
+  // Changes since the search began, most recent last.
+  trail: Vec<Undo>,
+  // Heap bytes of the line states saved on the trail.
+  trail_bytes: usize,
+  // The level whose choice is being propagated.
+  level: usize,
+  // The choice made at each level after the root.
+  decisions: Vec<Choice>,
+  // The levels responsible for the most recent dead end, and the lines which
+  // ran out of words there.
+  conflict: Levels,
+  dead: Vec<usize>,
+  // Sets of choices which together lead to a dead end, and the indices of
+  // the nogoods containing each choice.
+  nogoods: Vec<Vec<Choice>>,
+  nogood_index: HashMap<Choice, Vec<usize>>,
+  // How many dead ends each line has run out of words at, plus one.
+  weights: Vec<u32>,
+  // Quality scores for the words of each length, by id, for best-first fills.
+  scores: HashMap<usize, Vec<u32>>,
+}
+
+impl Solver {
+  fn new(puzzle: Arc<Puzzle>, dictionary: &Dictionary) -> Solver {
     let lengths: HashSet<_> = puzzle.lines.iter().map(|l| l.length()).collect();
-    let mut line_state_templates: HashMap<usize, LineState> = lengths
+    let mut indices: HashMap<usize, WordIndex> = lengths
       .into_iter()
-      .map(|l| (l, LineState::new(l)))
+      .map(|l| (l, WordIndex::new(l)))
       .collect();
     dictionary.visit_all(|_, s: &str| {
-      if let Some(line) = line_state_templates.get_mut(&s.len()) {
-        line.add(s);
+      if let Some(index) = indices.get_mut(&s.len()) {
+        index.add(s);
       }
     });
-    let line_state_templates: HashMap<usize, Rc<LineState>> = line_state_templates
+    let line_state_templates: HashMap<usize, LineState> = indices
       .into_iter()
-      .map(|(k, v)| (k, Rc::from(v)))
+      .map(|(l, mut index)| {
+        index.index_bits();
+        let bits = Candidates::Bits(index.all_bits());
+        (l, LineState::new(Arc::new(index), bits))
+      })
       .collect();
     Solver {
-      puzzle,
       line_states: puzzle
         .lines
         .iter()
         .map(|line| line_state_templates.get(&line.length()).unwrap().clone())
         .collect(),
+      weights: vec![1; puzzle.lines.len()],
+      puzzle,
+      committed: HashMap::new(),
+      used: HashMap::new(),
+      trail: vec![],
+      trail_bytes: 0,
+      level: 0,
+      decisions: vec![],
+      conflict: Levels::default(),
+      dead: vec![],
+      nogoods: vec![],
+      nogood_index: HashMap::new(),
+      scores: HashMap::new(),
     }
   }
 
@@ -218,208 +668,837 @@ impl<'a> Solver<'a> {
     }
   }
 
+  /// Orders the letters of `set` for cell `ci` by sampling, weighting each
+  /// letter by how many remaining words support it in both crossing lines.
+  fn sample_letters<R: Rng>(&self, ci: usize, set: &LetterSet, rng: &mut R) -> Vec<u8> {
+    let lis = &self.puzzle.cells[ci].lines;
+    let mut weighted: Vec<(u8, u64)> = set
+      .indices()
+      .map(|o| {
+        let support = |(li, pos): (u32, u8)| {
+          self.line_states[li as usize].letter_count(pos, o) as u64
+        };
+        (o, support(lis[0]) * support(lis[1]))
+      })
+      .collect();
+    let mut order = Vec::with_capacity(weighted.len());
+    while !weighted.is_empty() {
+      let total: u64 = weighted.iter().map(|(_, w)| w).sum();
+      let mut r = rng.gen_range(0, total);
+      let i = weighted
+        .iter()
+        .position(|(_, w)| {
+          if r < *w {
+            true
+          } else {
+            r -= w;
+            false
+          }
+        })
+        .unwrap();
+      order.push(weighted.swap_remove(i).0);
+    }
+    order
+  }
+
   fn commit_char(&mut self, cell_index: usize, ch: char) -> bool {
     self
       .commit_ord(cell_index, LetterSet::index(ch).unwrap())
       .is_some()
   }
 
-  fn commit_ord(&mut self, ci: usize, ord: u8) -> Option<usize> {
-    let cell = &self.puzzle.cells[ci];
-    let lis = &cell.lines;
-    let mut across = &mut self.line_states[lis[0].0 as usize];
-    let mut cost = 0;
-    if !across.position_letters[lis[0].1 as usize].contains_index(ord) {
-      return None;
+  /// Commits a letter for a cell or a word for a line. Returns the cost of
+  /// propagating it, or None on a dead end.
+  fn commit(&mut self, choice: Choice) -> Option<usize> {
+    match choice {
+      Choice::Letter(ci, o) => self.commit_ord(ci, o),
+      Choice::Word(li, id) => self.commit_word(li, id),
     }
-    cost += across.ords.len();
-    *across = Rc::from(across.with_chosen(ord, lis[0].1));
+  }
 
-    let mut down = &mut self.line_states[lis[1].0 as usize];
-    if !down.position_letters[lis[1].1 as usize].contains_index(ord) {
+  /// Narrows line `li` to the word `id` and propagates it through the
+  /// crossing lines.
+  fn commit_word(&mut self, li: usize, id: u32) -> Option<usize> {
+    let line = &self.line_states[li];
+    if !line.contains(id) {
+      self.conflict = line.reasons.clone();
+      self.conflict.insert(self.level);
+      self.dead = vec![li];
       return None;
     }
-    cost += down.ords.len();
-    *down = Rc::from(down.with_chosen(ord, lis[1].1));
+    let cost = line.length();
+    let mut dirty = vec![];
+    self.narrow_line(
+      li,
+      Candidates::Ids(vec![id]),
+      &Levels::default(),
+      &mut dirty,
+    )?;
+    Some(cost + self.propagate(dirty)?)
+  }
 
-    /* TODO: Confirm that the position_letters only changed the selected
-     * offset's letterset. If another position changed, the corresponding line
-     * for that position must be refreshed to filter out the affected words.
-     * This may cascade, even back to the original line.*/
-    Some(cost)
+  /// Narrows both lines through cell `ci` to words with `ord` there, and
+  /// propagates the effects. On a dead end, the partial changes are left on
+  /// the trail for the caller to undo.
+  fn commit_ord(&mut self, ci: usize, ord: u8) -> Option<usize> {
+    let puzzle = self.puzzle.clone();
+    let lis = &puzzle.cells[ci].lines;
+    for &(li, pos) in lis {
+      if !self.line_states[li as usize].position_letters[pos as usize].contains_index(ord) {
+        self.conflict = self.cell_reasons(ci);
+        self.conflict.insert(self.level);
+        self.dead = vec![li as usize];
+        return None;
+      }
+    }
+    let mut letter = LetterSet::default();
+    letter.insert_index(ord);
+    let mut cost = 0;
+    let mut dirty = vec![];
+    for &(li, pos) in lis {
+      let line = &self.line_states[li as usize];
+      cost += line.word_count() * line.length();
+      let candidates = self.narrowed(li as usize, Some((pos, &letter)));
+      self.narrow_line(li as usize, candidates, &Levels::default(), &mut dirty)?;
+    }
+    Some(cost + self.propagate(dirty)?)
   }
 
-  fn solve(self, budget: &mut usize, depth: usize) -> SolveResult {
-    if depth < 20 {
-      mv(0, 0);
-      addstr(&format!(
-        "\r{:*<}{}{} Choices                      ",
-        depth * 2,
-        "",
-        self.max_permutations()
-      ));
-      refresh();
+  /// The candidates for line `li` after restricting a position to a set of
+  /// letters, less the words which other lines have committed.
+  fn narrowed(&self, li: usize, restrict: Option<(u8, &LetterSet)>) -> Candidates {
+    let line = &self.line_states[li];
+    match self.used.get(&line.length()) {
+      // A settled line keeps its own committed word.
+      Some(used) if line.word_count() > 1 => line.narrowed(restrict, used),
+      _ => line.narrowed(restrict, &[]),
     }
-    let mut best_choice = None;
-    for ci in 0..self.puzzle.cells.len() {
-      let set = self.cell_set(ci);
-      let n = set.len();
-      if n == 0 {
-        return SolveResult::None;
+  }
+
+  /// Narrows line `li` to the given candidates, queueing the cell at every
+  /// position whose letters shrank. The narrowing is blamed on the current
+  /// level and the levels in `cause`. Fails if no words remain.
+  fn narrow_line(
+    &mut self,
+    li: usize,
+    candidates: Candidates,
+    cause: &Levels,
+    dirty: &mut Vec<usize>,
+  ) -> Option<()> {
+    let mut narrowed = LineState::new(self.line_states[li].words.clone(), candidates);
+    if narrowed.word_count() == self.line_states[li].word_count() {
+      return Some(());
+    }
+    narrowed.reasons = self.line_states[li].reasons.clone();
+    narrowed.reasons.union(cause);
+    if self.level > 0 {
+      narrowed.reasons.insert(self.level);
+    }
+    if narrowed.word_count() == 0 {
+      self.conflict = narrowed.reasons;
+      self.dead = vec![li];
+      return None;
+    }
+    let puzzle = self.puzzle.clone();
+    let old = std::mem::replace(&mut self.line_states[li], narrowed);
+    let line = &self.line_states[li];
+    for (pos, ci) in puzzle.lines[li].cell_indices.iter().enumerate() {
+      if line.position_letters[pos].len() < old.position_letters[pos].len() {
+        dirty.push(*ci as usize);
       }
-      if n == 1 {
-        continue;
+    }
+    let settled = line.word_count() == 1;
+    self.trail_bytes += old.heap_size();
+    self.trail.push(Undo::Line(li, old));
+    if settled {
+      self.settle(li, dirty)?;
+    }
+    Some(())
+  }
+
+  /// Commits the only word left in line `li` and removes it from every other
+  /// line of the same length. Fails if another line already committed it.
+  fn settle(&mut self, li: usize, dirty: &mut Vec<usize>) -> Option<()> {
+    let puzzle = self.puzzle.clone();
+    let line = &self.line_states[li];
+    let key = (line.length(), line.first().unwrap());
+    if let Some(&owner) = self.committed.get(&key) {
+      if owner == li {
+        return Some(());
       }
-      if let Some((smallest_n, _, _)) = best_choice {
-        if smallest_n <= n {
-          continue;
-        }
+      self.conflict = line.reasons.clone();
+      self.conflict.union(&self.line_states[owner].reasons);
+      self.dead = vec![li, owner];
+      return None;
+    }
+    let cause = line.reasons.clone();
+    self.committed.insert(key, li);
+    let used = self.used.entry(key.0).or_default();
+    let at = used.binary_search(&key.1).unwrap_err();
+    used.insert(at, key.1);
+    self.trail.push(Undo::Commit(key));
+    for (other, line) in puzzle.lines.iter().enumerate() {
+      if other != li && line.length() == key.0 && self.line_states[other].contains(key.1) {
+        let candidates = self.narrowed(other, None);
+        self.narrow_line(other, candidates, &cause, dirty)?;
       }
-      best_choice = Some((n, ci, set));
     }
-    if let Some((_, ci, set)) = best_choice {
-      for o in set.indices() {
-        // TODO:Shuffle
-        let mut child = self.clone();
-        if let Some(cost) = child.commit_ord(ci, o) {
-          if let Some(remaining) = budget.checked_sub(cost) {
-            *budget = remaining;
-          } else {
-            return SolveResult::Incomplete(
-              (0..self.puzzle.cells.len())
-                .filter_map(|ci| self.solved_char(ci).map(|ch| (ci, ch)))
-                .collect(),
-            );
-          }
-        } else {
-          // Direct constraint always works, but indirect effects could reveal a dead end.
-          continue;
-        }
-        let result = child.solve(budget, depth + 1);
-        match &result {
-          SolveResult::Solution(_) | SolveResult::Incomplete(_) => {
-            return result;
-          }
-          _ => {}
+    Some(())
+  }
+
+  /// Re-filters both lines through each dirty cell until every cell's across
+  /// and down letters agree. This may cascade, even back to the line which
+  /// started it. Returns the cost of the filtering, or None on a dead end.
+  fn propagate(&mut self, mut dirty: Vec<usize>) -> Option<usize> {
+    let puzzle = self.puzzle.clone();
+    let mut cost = 0;
+    while let Some(ci) = dirty.pop() {
+      let set = self.cell_set(ci);
+      if set.len() == 0 {
+        self.conflict = self.cell_reasons(ci);
+        self.dead = puzzle.cells[ci]
+          .lines
+          .iter()
+          .map(|&(li, _)| li as usize)
+          .collect();
+        return None;
+      }
+      let lis = &puzzle.cells[ci].lines;
+      for (i, &(li, pos)) in lis.iter().enumerate() {
+        let line = &self.line_states[li as usize];
+        if line.position_letters[pos as usize].len() > set.len() {
+          cost += line.word_count() * line.length();
+          let cause = self.line_states[lis[1 - i].0 as usize].reasons.clone();
+          let candidates = self.narrowed(li as usize, Some((pos, &set)));
+          self.narrow_line(li as usize, candidates, &cause, &mut dirty)?;
         }
       }
-      SolveResult::None
-    } else {
-      SolveResult::Solution(
-        (0..self.puzzle.cells.len())
-          .map(|ci| (ci, self.solved_char(ci).unwrap()))
-          .collect(),
-      )
     }
+    Some(cost)
   }
-}
 
-#[derive(Clone)]
-pub enum Square {
-  Empty,
-  Fixed(char),
-  Solved(char),
-}
+  /// The levels whose choices narrowed either line through cell `ci`.
+  fn cell_reasons(&self, ci: usize) -> Levels {
+    let lis = &self.puzzle.cells[ci].lines;
+    let mut reasons = self.line_states[lis[0].0 as usize].reasons.clone();
+    reasons.union(&self.line_states[lis[1].0 as usize].reasons);
+    reasons
+  }
 
-pub struct Grid {
-  // Walls are missing squares.
-  squares: HashMap<(usize, usize), Square>,
-}
+  /// Whether a choice already holds: its cell is down to its letter, or its
+  /// line to its word.
+  fn holds(&self, choice: Choice) -> bool {
+    match choice {
+      Choice::Letter(ci, o) => {
+        let set = self.cell_set(ci);
+        set.len() == 1 && set.contains_index(o)
+      }
+      Choice::Word(li, id) => {
+        let line = &self.line_states[li];
+        line.word_count() == 1 && line.contains(id)
+      }
+    }
+  }
 
-impl Grid {
-  pub fn new_rectangle(width: usize, height: usize) -> Grid {
-    Grid {
-      squares: (1..=height)
-        .flat_map(|y| (1..=width).map(move |x| ((x, y), Square::Empty)))
-        .collect(),
+  /// The levels whose choices narrowed the cell or line of `choice`.
+  fn choice_reasons(&self, choice: Choice) -> Levels {
+    match choice {
+      Choice::Letter(ci, _) => self.cell_reasons(ci),
+      Choice::Word(li, _) => self.line_states[li].reasons.clone(),
     }
   }
 
-  pub fn new_circle(outer: i64, inner: i64) -> Grid {
-    let c = outer + 1;
-    Grid {
-      squares: (1..=(c * 2))
-        .flat_map(|y| (1..=(c * 2)).map(move |x| (x, y)))
-        .filter_map(|(x, y)| {
-          let dx = x - c;
-          let dy = y - c;
-          let r = dx * dx + dy * dy;
-          if r <= inner * inner || r >= outer * outer + outer {
-            None
-          } else {
-            Some((x as usize, y as usize))
-          }
-        })
-        .map(|p| (p, Square::Empty))
-        .collect(),
+  /// Whether `choice` completes a learned nogood, given the choices which
+  /// already hold. If so, the nogood's choices are blamed for the conflict.
+  fn violates_nogood(&mut self, choice: Choice) -> bool {
+    let ids = match self.nogood_index.get(&choice) {
+      Some(ids) => ids,
+      None => return false,
+    };
+    for id in ids {
+      let nogood = &self.nogoods[*id];
+      if nogood.iter().all(|&choice| self.holds(choice)) {
+        let mut conflict = Levels::default();
+        for &choice in nogood {
+          conflict.union(&self.choice_reasons(choice));
+        }
+        self.conflict = conflict;
+        return true;
+      }
     }
+    false
   }
 
-  pub fn new_diamond(outer: i64, inner: i64) -> Grid {
-    let c = outer + 1;
-    Grid {
-      squares: (1..=(c * 2))
-        .flat_map(|y| (1..=(c * 2)).map(move |x| (x, y)))
-        .filter_map(|(x, y)| {
-          let mut dx = (x - c).abs();
-          let mut dy = (y - c).abs();
-          if dx == 0 {
-            dy += 1;
-          }
-          if dy == 0 {
-            dy += 1;
-          }
-          let r = dx + dy;
-          if r <= inner || r > outer {
-            None
-          } else {
-            Some((x as usize, y as usize))
-          }
-        })
-        .map(|p| (p, Square::Empty))
-        .collect(),
+  /// Records that the choices made at the levels in `conflict` can't all
+  /// hold together, so no other subtree needs to rediscover it.
+  fn learn(&mut self, conflict: &Levels) {
+    let nogood: Vec<Choice> = conflict.iter().map(|level| self.decisions[level - 1]).collect();
+    // Long nogoods rarely recur, and are costly to check.
+    if nogood.is_empty() || nogood.len() > MAX_NOGOOD_LEN {
+      return;
     }
+    self.add_nogood(nogood);
   }
 
-  pub fn get_outline(&self) -> HashSet<(usize, usize)> {
-    let mut outline = HashSet::new();
-    for ((x, y), _) in &self.squares {
-      for dx in 0..=2 {
-        let x = x + dx - 1;
-        for dy in 0..=2 {
-          let y = y + dy - 1;
-          let loc = (x, y);
-          if !self.squares.contains_key(&loc) {
-            outline.insert(loc);
-          }
+  fn add_nogood(&mut self, nogood: Vec<Choice>) {
+    if self.nogoods.len() >= MAX_NOGOODS {
+      // Forget the older half, which the search has most likely moved on
+      // from, rather than check ever more nogoods at each commit.
+      self.nogoods.drain(..MAX_NOGOODS / 2);
+      self.nogood_index.clear();
+      for (id, nogood) in self.nogoods.iter().enumerate() {
+        for literal in nogood {
+          self.nogood_index.entry(*literal).or_default().push(id);
         }
       }
     }
-    outline
+    let id = self.nogoods.len();
+    for literal in &nogood {
+      self.nogood_index.entry(*literal).or_default().push(id);
+    }
+    self.nogoods.push(nogood);
   }
 
-  pub fn solve(&mut self, dictionary: &Dictionary, _rng: &mut ThreadRng) -> bool {
-    let puzzle = Puzzle::new(self);
-    let mut solver = Solver::new(&puzzle, dictionary);
-    for (ci, position) in puzzle.cell_positions.iter().enumerate() {
-      if let Square::Fixed(ch) = self.squares[position] {
-        if !solver.commit_char(ci, ch) {
-          return false;
+  /// Takes back every choice, back to the state the search began from.
+  fn restart(&mut self) {
+    self.undo(0);
+    self.level = 0;
+    self.decisions.clear();
+  }
+
+  /// Unwinds the trail back to `mark`, undoing every change made since.
+  fn undo(&mut self, mark: usize) {
+    while self.trail.len() > mark {
+      match self.trail.pop().unwrap() {
+        Undo::Line(li, state) => {
+          self.trail_bytes -= state.heap_size();
+          self.line_states[li] = state;
+        }
+        Undo::Commit(key) => {
+          self.committed.remove(&key);
+          let used = self.used.get_mut(&key.0).unwrap();
+          let at = used.binary_search(&key.1).unwrap();
+          used.remove(at);
         }
       }
     }
-    for square in self.squares.values_mut() {
-      if let Square::Solved(_) = *square {
-        *square = Square::Empty;
+  }
+
+  /// Filters every line against its crossing lines until each cell's letters
+  /// can be placed by both of its lines. None if the grid is impossible.
+  fn prefilter(&mut self) -> Option<()> {
+    let mut dirty: Vec<_> = (0..self.puzzle.cells.len()).collect();
+    for li in 0..self.line_states.len() {
+      if self.line_states[li].word_count() == 1 {
+        self.settle(li, &mut dirty)?;
       }
     }
-    let empty = [];
-    let result = solver.solve(&mut 40000000000, 0);
-    let (ci_chars, ret) = match &result {
-      SolveResult::Incomplete(chars) => (&chars[..], false),
-      SolveResult::Solution(chars) => (&chars[..], true),
-      SolveResult::None => (&empty[..], false),
+    self.propagate(dirty)?;
+    Some(())
+  }
+
+  /// Depth-first search from the state described by `stack`, which must
+  /// already be applied to the solver. An empty stack starts from the root.
+  /// When a limit stops it, `stack` is left where the search stopped.
+  fn run<R: Rng>(&mut self, search: &mut Search<R>, stack: &mut Vec<Frame>) -> SolveResult {
+    // Whether the solver is at a node which hasn't been branched on yet.
+    let mut fresh = match stack.last() {
+      Some(frame) => frame.chosen.is_some(),
+      None => true,
     };
+    loop {
+      if fresh {
+        search.stopped = search.limit_reached();
+        let stop = search.stop.as_ref().is_some_and(CancelToken::is_cancelled);
+        if search.stopped.is_some() || search.nodes >= search.max_nodes || stop {
+          return SolveResult::Incomplete(self.settled());
+        }
+        if let Some(split) = &search.split {
+          self.offer(split, stack);
+        }
+        let depth = stack.len();
+        search.nodes += 1;
+        search.stats.nodes += 1;
+        search.remember(self);
+        if search.eta.is_none() && search.observer.wants_eta() {
+          // Probed with a generator of its own, so that asking for an ETA
+          // doesn't change which fill a seeded solve finds.
+          let eta = self.estimate(ETA_PROBES, &mut StdRng::seed_from_u64(0)).eta();
+          search.eta = Some((Instant::now(), eta));
+        }
+        if depth < 20 {
+          search.observer.progress(&Progress {
+            depth,
+            nodes: search.nodes,
+            backtracks: search.backtracks,
+            remaining: self.max_permutations(),
+            eta: search.eta.map(|(start, eta)| match eta {
+              // A solve which won't finish doesn't get any closer to it.
+              Duration::MAX => eta,
+              eta => eta.saturating_sub(start.elapsed()),
+            }),
+          });
+        }
+        match self.branch(search.strategy, search.rng) {
+          Err(Branch::Complete) => return SolveResult::Solution(self.solution()),
+          Err(_) => {
+            self.conflict = Levels::default();
+            for level in 1..=depth {
+              self.conflict.insert(level);
+            }
+            if !self.backtrack(search, stack) {
+              return SolveResult::None;
+            }
+          }
+          Ok((mut untried, reasons)) => {
+            // Conflict-directed backjumping: collect the levels responsible
+            // for every choice here failing, starting with the levels which
+            // ruled out other choices before this branch.
+            untried.reverse();
+            stack.push(Frame {
+              chosen: None,
+              untried,
+              conflicts: reasons,
+            });
+          }
+        }
+      }
+      fresh = false;
+      let level = stack.len();
+      let frame = match stack.last_mut() {
+        Some(frame) => frame,
+        None => return SolveResult::None,
+      };
+      let choice = match frame.untried.pop() {
+        Some(choice) => choice,
+        None => {
+          let mut conflicts = std::mem::take(&mut frame.conflicts);
+          conflicts.remove(level);
+          if search.backjumping {
+            self.learn(&conflicts);
+          }
+          self.conflict = conflicts;
+          stack.pop();
+          if !self.backtrack(search, stack) {
+            return SolveResult::None;
+          }
+          continue;
+        }
+      };
+      let mark = self.trail.len();
+      self.level = level;
+      self.decisions.push(choice);
+      let start = Instant::now();
+      let committed = match self.commit(choice) {
+        Some(_) if self.violates_nogood(choice) => None,
+        None => {
+          self.weigh_failure();
+          None
+        }
+        committed => committed,
+      };
+      search.committed(self, mark, start);
+      match committed {
+        Some(_) => {
+          search.step(self, StepKind::Commit, level, choice);
+          frame.chosen = Some((choice, mark));
+          fresh = true;
+        }
+        None => {
+          // Propagation through the crossing lines revealed a dead end.
+          self.undo(mark);
+          self.decisions.pop();
+          search.step(self, StepKind::Fail, level, choice);
+          search.backtracked(level);
+          frame.conflicts.union(&self.conflict);
+        }
+      }
+    }
+  }
+
+  /// Takes back the choice made by the deepest frame, after the subtree
+  /// below it failed because of the levels in `self.conflict`. Returns false
+  /// once there are no frames left to try other letters in.
+  fn backtrack<R: Rng>(&mut self, search: &mut Search<R>, stack: &mut Vec<Frame>) -> bool {
+    loop {
+      let level = stack.len();
+      let frame = match stack.last_mut() {
+        Some(frame) => frame,
+        None => return false,
+      };
+      let (choice, mark) = frame.chosen.take().unwrap();
+      self.undo(mark);
+      self.decisions.pop();
+      search.backtracked(level);
+      search.step(self, StepKind::Backtrack, level, choice);
+      if self.conflict.contains(level) || !search.backjumping {
+        frame.conflicts.union(&self.conflict);
+        return true;
+      }
+      // The subtree failed regardless of this choice, so every other choice
+      // would too. Jump straight back to the culprit.
+      stack.pop();
+    }
+  }
+
+  /// Reapplies the choices made by the frames of a paused search.
+  fn replay_frames(&mut self, stack: &mut [Frame]) -> Option<()> {
+    for (i, frame) in stack.iter_mut().enumerate() {
+      if let Some((choice, _)) = frame.chosen {
+        let mark = self.trail.len();
+        self.level = i + 1;
+        self.decisions.push(choice);
+        self.commit(choice)?;
+        frame.chosen = Some((choice, mark));
+      }
+    }
+    Some(())
+  }
+
+  /// The letters of every cell which is down to one letter.
+  fn settled(&self) -> Vec<(usize, char)> {
+    (0..self.puzzle.cells.len())
+      .filter_map(|ci| self.solved_char(ci).map(|ch| (ci, ch)))
+      .collect()
+  }
+
+  fn solution(&self) -> Vec<(usize, char)> {
+    (0..self.puzzle.cells.len())
+      .map(|ci| (ci, self.solved_char(ci).unwrap()))
+      .collect()
+  }
+}
+
+/// A complete fill, as the letter at each square's position.
+pub type Fill = HashMap<(usize, usize), char>;
+
+/// Iterates over every distinct fill of a grid, depth first. Branches at a
+/// cell take different letters there, so no fill is yielded twice.
+pub struct Solutions {
+  solver: Option<Solver>,
+  // Each frame holds the cell being branched on, the letters left to try in
+  // it, and the trail mark to undo to before trying each one.
+  stack: Vec<(usize, Vec<u8>, usize)>,
+  // A grid which needed no branching at all has exactly one fill.
+  pending: Option<Fill>,
+}
+
+impl Solutions {
+  fn new(solver: Option<Solver>) -> Solutions {
+    let mut solutions = Solutions {
+      solver,
+      stack: vec![],
+      pending: None,
+    };
+    solutions.pending = solutions.branch();
+    solutions
+  }
+
+  /// Pushes a frame for the solver's next branch. Returns the solver's fill
+  /// if it is already complete.
+  fn branch(&mut self) -> Option<Fill> {
+    let solver = self.solver.as_ref()?;
+    match solver.pick_cell(VariableOrder::Fewest) {
+      Branch::DeadEnd => None,
+      Branch::Complete => Some(
+        solver
+          .solution()
+          .into_iter()
+          .map(|(ci, ch)| (solver.puzzle.cell_positions[ci], ch))
+          .collect(),
+      ),
+      Branch::At((ci, set)) => {
+        let mark = solver.trail.len();
+        self.stack.push((ci, set.indices().collect(), mark));
+        None
+      }
+    }
+  }
+}
+
+impl Iterator for Solutions {
+  type Item = Fill;
+
+  fn next(&mut self) -> Option<Fill> {
+    if let Some(fill) = self.pending.take() {
+      return Some(fill);
+    }
+    loop {
+      let (ci, letters, mark) = self.stack.last_mut()?;
+      let solver = self.solver.as_mut()?;
+      solver.undo(*mark);
+      let o = match letters.pop() {
+        Some(o) => o,
+        None => {
+          self.stack.pop();
+          continue;
+        }
+      };
+      if solver.commit_ord(*ci, o).is_none() {
+        continue;
+      }
+      if let Some(fill) = self.branch() {
+        return Some(fill);
+      }
+    }
+  }
+}
+
+/// How many candidates the fixed squares and the prefilter removed from one
+/// line, as `Grid::prefilter` reports it.
+pub struct LineFilter {
+  pub start: (usize, usize),
+  pub direction: u8,
+  pub removed: usize,
+  pub remaining: usize,
+}
+
+#[derive(Clone)]
+pub enum Square {
+  Empty,
+  Fixed(char),
+  Solved(char),
+}
+
+#[derive(Clone)]
+pub struct Grid {
+  // Walls are missing squares.
+  squares: HashMap<(usize, usize), Square>,
+}
+
+impl Grid {
+  pub fn new_rectangle(width: usize, height: usize) -> Grid {
+    Grid {
+      squares: (1..=height)
+        .flat_map(|y| (1..=width).map(move |x| ((x, y), Square::Empty)))
+        .collect(),
+    }
+  }
+
+  pub fn new_circle(outer: i64, inner: i64) -> Grid {
+    let c = outer + 1;
+    Grid {
+      squares: (1..=(c * 2))
+        .flat_map(|y| (1..=(c * 2)).map(move |x| (x, y)))
+        .filter_map(|(x, y)| {
+          let dx = x - c;
+          let dy = y - c;
+          let r = dx * dx + dy * dy;
+          if r <= inner * inner || r >= outer * outer + outer {
+            None
+          } else {
+            Some((x as usize, y as usize))
+          }
+        })
+        .map(|p| (p, Square::Empty))
+        .collect(),
+    }
+  }
+
+  pub fn new_diamond(outer: i64, inner: i64) -> Grid {
+    let c = outer + 1;
+    Grid {
+      squares: (1..=(c * 2))
+        .flat_map(|y| (1..=(c * 2)).map(move |x| (x, y)))
+        .filter_map(|(x, y)| {
+          let mut dx = (x - c).abs();
+          let mut dy = (y - c).abs();
+          if dx == 0 {
+            dy += 1;
+          }
+          if dy == 0 {
+            dy += 1;
+          }
+          let r = dx + dy;
+          if r <= inner || r > outer {
+            None
+          } else {
+            Some((x as usize, y as usize))
+          }
+        })
+        .map(|p| (p, Square::Empty))
+        .collect(),
+    }
+  }
+
+  pub fn get_outline(&self) -> HashSet<(usize, usize)> {
+    let mut outline = HashSet::new();
+    for ((x, y), _) in &self.squares {
+      for dx in 0..=2 {
+        let x = x + dx - 1;
+        for dy in 0..=2 {
+          let y = y + dy - 1;
+          let loc = (x, y);
+          if !self.squares.contains_key(&loc) {
+            outline.insert(loc);
+          }
+        }
+      }
+    }
+    outline
+  }
+
+  /// Builds a solver with the fixed squares committed and every line
+  /// prefiltered, along with how many candidates each line lost to them.
+  /// None if the grid can't be filled.
+  fn prepare(&self, puzzle: &Arc<Puzzle>, dictionary: &Dictionary) -> Option<(Solver, Vec<usize>)> {
+    self.try_prepare(puzzle, dictionary).ok()
+  }
+
+  /// Like `prepare`, but on failure returns the lines left with no words.
+  fn try_prepare(
+    &self,
+    puzzle: &Arc<Puzzle>,
+    dictionary: &Dictionary,
+  ) -> Result<(Solver, Vec<usize>), Vec<usize>> {
+    let mut solver = Solver::new(puzzle.clone(), dictionary);
+    let before: Vec<_> = solver.line_states.iter().map(|l| l.word_count()).collect();
+    for (ci, position) in puzzle.cell_positions.iter().enumerate() {
+      if let Square::Fixed(ch) = self.squares[position] {
+        if !solver.commit_char(ci, ch) {
+          return Err(solver.dead);
+        }
+      }
+    }
+    if solver.prefilter().is_none() {
+      return Err(solver.dead);
+    }
+    let lost = before
+      .iter()
+      .zip(&solver.line_states)
+      .map(|(before, line)| before - line.word_count())
+      .collect();
+    // Nothing before the search is ever undone.
+    solver.trail.clear();
+    solver.trail_bytes = 0;
+    Ok((solver, lost))
+  }
+
+  /// How many candidates each entry has left after the fixed squares and the
+  /// prefilter, and how many they removed. If that leaves an entry with no
+  /// words, returns the entries left empty instead.
+  pub fn prefilter(&self, dictionary: &Dictionary) -> Result<Vec<LineFilter>, Vec<Entry>> {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let (solver, lost) = self
+      .try_prepare(&puzzle, dictionary)
+      .map_err(|dead| failure::entries(&puzzle, dead))?;
+    Ok(
+      puzzle
+        .lines
+        .iter()
+        .zip(lost)
+        .zip(&solver.line_states)
+        .map(|((line, removed), state)| LineFilter {
+          start: puzzle.cell_positions[line.cell_indices[0] as usize],
+          direction: line.direction,
+          removed,
+          remaining: state.word_count(),
+        })
+        .collect(),
+    )
+  }
+
+  /// Every distinct fill of the grid which keeps its fixed squares.
+  pub fn solutions(&self, dictionary: &Dictionary) -> Solutions {
+    let puzzle = Arc::new(Puzzle::new(self));
+    Solutions::new(self.prepare(&puzzle, dictionary).map(|(solver, _)| solver))
+  }
+
+  /// Counts the distinct fills of the grid, stopping once `limit` are found.
+  pub fn count_solutions(&self, dictionary: &Dictionary, limit: usize) -> usize {
+    self.solutions(dictionary).take(limit).count()
+  }
+
+  /// Solves the grid depth-first within the limits of `options`, branching
+  /// as `strategy` says. If a limit stops it, the squares settled so far are
+  /// filled in and the search can be resumed. With `options.keep_closest`,
+  /// the squares filled in when a limit stops it or it finds no fill are
+  /// those of the closest partial fill it came across instead. Returns how
+  /// the solve ended along with its stats.
+  pub fn solve<R: Rng>(
+    &mut self,
+    dictionary: &Dictionary,
+    strategy: Strategy,
+    options: &SolveOptions,
+    rng: &mut R,
+    observer: &mut dyn Observer,
+  ) -> (Outcome, SolveStats) {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let solver = match self.prepare(&puzzle, dictionary) {
+      Some((solver, _)) => solver,
+      None => {
+        let failure = self.explain(&puzzle, dictionary, false, options);
+        return (Outcome::Failed(failure), SolveStats::default());
+      }
+    };
+    let search = Search::new(strategy, options, rng, observer);
+    self.run_search(&puzzle, dictionary, solver, vec![], search)
+  }
+
+  fn run_search<R: Rng>(
+    &mut self,
+    puzzle: &Arc<Puzzle>,
+    dictionary: &Dictionary,
+    mut solver: Solver,
+    mut stack: Vec<Frame>,
+    mut search: Search<R>,
+  ) -> (Outcome, SolveStats) {
+    self.clear_solved();
+    let start = Instant::now();
+    let result = solver.run(&mut search, &mut stack);
+    search.stats.elapsed = start.elapsed();
+    let outcome = match result {
+      SolveResult::Solution(chars) => {
+        self.write_solved(puzzle, &chars);
+        Outcome::Solved
+      }
+      SolveResult::Incomplete(chars) => {
+        match &search.closest {
+          Some((_, closest)) => self.write_solved(puzzle, closest),
+          None => self.write_solved(puzzle, &chars),
+        }
+        let limit = search.stopped.unwrap_or(Limit::Nodes);
+        Outcome::Paused(self.paused(puzzle, &search, &mut solver, stack), limit)
+      }
+      SolveResult::None => {
+        if let Some((_, closest)) = &search.closest {
+          self.write_solved(puzzle, closest);
+        }
+        Outcome::Failed(self.explain(puzzle, dictionary, true, &search.options))
+      }
+    };
+    (outcome, search.stats)
+  }
+
+  /// Describes how a solve ended, saving the search if it was paused.
+  fn report(&self, outcome: Outcome) -> String {
+    match outcome {
+      Outcome::Solved => "Solved!".to_string(),
+      Outcome::Failed(failure) => format!("Failed! {}", failure),
+      Outcome::Paused(state, limit) => {
+        let saved = File::create(CHECKPOINT).and_then(|mut file| state.save(&mut file));
+        match saved {
+          Ok(()) => format!(
+            "Paused {} after {} nodes, '>' resumes",
+            limit,
+            state.nodes()
+          ),
+          Err(e) => format!("Paused {}, can't save {}: {}", limit, CHECKPOINT, e),
+        }
+      }
+      Outcome::Stopped(limit) => format!("Stopped {}", limit),
+    }
+  }
+
+  fn clear_solved(&mut self) {
+    for square in self.squares.values_mut() {
+      if let Square::Solved(_) = *square {
+        *square = Square::Empty;
+      }
+    }
+  }
+
+  fn write_solved(&mut self, puzzle: &Puzzle, ci_chars: &[(usize, char)]) {
     for (ci, ch) in ci_chars {
       let pos = puzzle.cell_positions[*ci];
       let square = self.squares.get_mut(&pos).unwrap();
@@ -432,7 +1511,16 @@ impl Grid {
         }
       }
     }
-    ret
+  }
+
+  /// What the TUI shows a solve's progress with. A stepper stops the solve
+  /// through `cancel` if it's told to.
+  fn observer(&self, step_through: bool, cancel: &Option<CancelToken>) -> Box<dyn Observer> {
+    if step_through {
+      Box::new(Stepper::new(self, cancel.clone()))
+    } else {
+      Box::new(StatusLine)
+    }
   }
 
   pub fn set_square(&mut self, x: usize, y: usize, square: Square) {
@@ -448,6 +1536,166 @@ impl Grid {
   }
 }
 
+/// Shows a solve's progress on the TUI's status line.
+struct StatusLine;
+
+impl StatusLine {
+  fn text(progress: &Progress) -> String {
+    let eta = match progress.eta {
+      Some(Duration::MAX) => "never".to_string(),
+      Some(eta) => format!("{:.0?}", eta),
+      None => "?".to_string(),
+    };
+    format!(
+      "\r{:*<5$}{:e} Choices, ETA {}, {} nodes, {} backtracks                      ",
+      "",
+      progress.remaining,
+      eta,
+      progress.nodes,
+      progress.backtracks,
+      progress.depth * 2
+    )
+  }
+}
+
+impl Observer for StatusLine {
+  fn progress(&mut self, progress: &Progress) {
+    mv(0, 0);
+    addstr(&StatusLine::text(progress));
+    refresh();
+  }
+
+  fn wants_eta(&self) -> bool {
+    true
+  }
+}
+
+/// Shows a solve's progress like `StatusLine`, and cancels it through `cancel`
+/// if Escape is pressed meanwhile, checking for keys every so often without
+/// waiting for one.
+struct Interruptible {
+  cancel: CancelToken,
+  checked: Instant,
+}
+
+impl Interruptible {
+  fn new(cancel: CancelToken) -> Interruptible {
+    Interruptible {
+      cancel,
+      checked: Instant::now(),
+    }
+  }
+}
+
+impl Observer for Interruptible {
+  fn progress(&mut self, progress: &Progress) {
+    if self.checked.elapsed() < KEY_CHECK_INTERVAL {
+      return;
+    }
+    self.checked = Instant::now();
+    mv(0, 0);
+    addstr(&StatusLine::text(progress));
+    addstr("Esc stops");
+    refresh();
+    // As in `Stepper`, the keypad keeps arrow keys from reading as Escape.
+    nodelay(stdscr(), true);
+    keypad(stdscr(), true);
+    let key = getch();
+    keypad(stdscr(), false);
+    nodelay(stdscr(), false);
+    if key == 0x1b {
+      self.cancel.cancel();
+    }
+  }
+}
+
+/// How far a `Stepper` lets a solve run before pausing it again.
+#[derive(Clone, Copy, PartialEq)]
+enum Stepping {
+  Step,
+  ToBacktrack,
+  Run,
+}
+
+/// Shows a solve on the TUI's grid one step at a time, with the letters it
+/// has settled underlined and the square it chose highlighted, and pauses
+/// for a key after each step. Escape stops the solve through `cancel`.
+struct Stepper {
+  fixed: HashSet<(usize, usize)>,
+  stepping: Stepping,
+  cancel: Option<CancelToken>,
+}
+
+impl Stepper {
+  fn new(grid: &Grid, cancel: Option<CancelToken>) -> Stepper {
+    let squares = grid.squares.iter();
+    Stepper {
+      fixed: squares
+        .filter(|(_, square)| matches!(square, Square::Fixed(_)))
+        .map(|(position, _)| *position)
+        .collect(),
+      stepping: Stepping::Step,
+      cancel,
+    }
+  }
+}
+
+impl Observer for Stepper {
+  fn progress(&mut self, progress: &Progress) {
+    if self.stepping == Stepping::Run {
+      StatusLine.progress(progress);
+    }
+  }
+
+  fn step(&mut self, step: &Step) {
+    if self.stepping == Stepping::ToBacktrack && step.kind == StepKind::Commit {
+      return;
+    }
+    for (position, letter) in &step.letters {
+      let tentative = !self.fixed.contains(position);
+      let mut attributes = if tentative { A_UNDERLINE() } else { A_NORMAL() };
+      if step.chosen.contains(position) {
+        attributes |= A_REVERSE();
+      }
+      mv(position.1 as i32 + 1, position.0 as i32);
+      attron(attributes);
+      addch(match letter {
+        Some(ch) if tentative => ch.to_ascii_lowercase(),
+        Some(ch) => *ch,
+        None => ' ',
+      } as u32);
+      attroff(attributes);
+    }
+    mv(0, 0);
+    addstr(&format!(
+      "\r{:?} at depth {}: space steps, 'b' runs to a backtrack, 'c' continues, Esc stops",
+      step.kind, step.depth
+    ));
+    clrtoeol();
+    refresh();
+    // With the keypad on, arrow and function keys read as single codes past
+    // every byte rather than as escape sequences, so 0x1b is only Escape.
+    keypad(stdscr(), true);
+    let key = getch();
+    keypad(stdscr(), false);
+    self.stepping = match u8::try_from(key) {
+      Ok(b'b') => Stepping::ToBacktrack,
+      Ok(b'c') => Stepping::Run,
+      Ok(0x1b) => {
+        if let Some(cancel) = &self.cancel {
+          cancel.cancel();
+        }
+        Stepping::Run
+      }
+      _ => Stepping::Step,
+    };
+  }
+
+  fn wants_steps(&self) -> bool {
+    self.stepping != Stepping::Run
+  }
+}
+
 impl View for Grid {
   fn cursor(&self, x: i32, y: i32) {
     mv(y, x);
@@ -456,6 +1704,35 @@ impl View for Grid {
     let (mut x, mut y) = (1, 1);
     let mut rng = rand::thread_rng();
     let mut downward = false;
+    let mut strategy = Strategy::default();
+    let mut restarts = Restarts::Luby { unit: 100 };
+    let mut objective = Objective::Minimum;
+    let mut trace = None;
+    let mut step_through = false;
+    // The squares to refill, and the last one toggled, which a rectangle is
+    // drawn from.
+    let mut region = HashSet::new();
+    let mut corner = None;
+    // A solve that runs out of time shows the entries it got furthest with,
+    // and one that fails shows the fixed squares to blame. Stepping through
+    // a solve takes as long as it takes, so it's cancelled rather than given
+    // a deadline.
+    let solve_options = |trace: &Option<Trace>, step_through: bool| {
+      let limits = if step_through {
+        SolveOptions {
+          cancel: Some(CancelToken::new()),
+          ..SolveOptions::default()
+        }
+      } else {
+        SolveOptions::within(SOLVE_TIME)
+      };
+      SolveOptions {
+        keep_closest: Some(Closeness::SettledEntries),
+        trace: trace.clone(),
+        explain: true,
+        ..limits
+      }
+    };
     let mut msg_line = 0;
     let dictionary = english_scrabble_dict().ok().unwrap();
     loop {
@@ -466,6 +1743,9 @@ impl View for Grid {
         y = 2;
       }
       self.render(0, 1);
+      for &(x, y) in &region {
+        mvchgat(y as i32 + 1, x as i32, 1, A_REVERSE(), 0);
+      }
       self.cursor(x, y);
       let input = getch() as u8;
       let u = x as usize;
@@ -478,10 +1758,306 @@ impl View for Grid {
         }
         0xa => {
           // enter
-          if self.solve(&dictionary, &mut rng) {
-            Some(format!("Solved!"))
+          let seed = rng.gen();
+          let options = solve_options(&trace, step_through);
+          let (outcome, stats) = self.solve(
+            &dictionary,
+            strategy,
+            &options,
+            &mut StdRng::seed_from_u64(seed),
+            &mut *self.observer(step_through, &options.cancel),
+          );
+          Some(format!(
+            "{} (seed {}, {} nodes in {:.0?}, {:.0?} propagating)",
+            self.report(outcome),
+            seed,
+            stats.nodes,
+            stats.elapsed,
+            stats.propagation_time
+          ))
+        }
+        0x40 => {
+          // at sign: solve with Luby restarts
+          let seed = rng.gen();
+          let options = solve_options(&trace, step_through);
+          let (outcome, stats) = self.solve_restarting(
+            &dictionary,
+            strategy,
+            restarts,
+            &options,
+            &mut StdRng::seed_from_u64(seed),
+            &mut *self.observer(step_through, &options.cancel),
+          );
+          Some(format!(
+            "{} (seed {}, {} nodes in {:.0?})",
+            self.report(outcome),
+            seed,
+            stats.nodes,
+            stats.elapsed
+          ))
+        }
+        0x5c => {
+          // backslash: switch stepping through solves on or off
+          step_through = !step_through;
+          if step_through {
+            Some("Stepping through solves".to_string())
+          } else {
+            Some("Solving at full speed".to_string())
+          }
+        }
+        0x3b => {
+          // semicolon: start or stop tracing solves
+          if trace.take().is_some() {
+            Some("Tracing off".to_string())
+          } else {
+            match File::create(TRACE) {
+              Ok(file) => {
+                trace = Some(Trace::new(BufWriter::new(file)));
+                Some(format!("Tracing solves to {}", TRACE))
+              }
+              Err(e) => Some(format!("Can't create {}: {}", TRACE, e)),
+            }
+          }
+        }
+        0x5e => {
+          // caret: switch restart schedule
+          restarts = match restarts {
+            Restarts::Luby { .. } => Restarts::Geometric {
+              first: 100,
+              factor: 1.5,
+            },
+            Restarts::Geometric { .. } => Restarts::Luby { unit: 100 },
+          };
+          Some(format!("{:?}", restarts))
+        }
+        0x26 => {
+          // ampersand: solve on every core, splitting the search
+          let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+          let options = solve_options(&trace, false);
+          let (outcome, stats) =
+            self.solve_parallel(&dictionary, strategy, threads, &options, &mut rng);
+          Some(format!(
+            "{} on {} threads ({} nodes in {:.0?})",
+            self.report(outcome),
+            threads,
+            stats.nodes,
+            stats.elapsed
+          ))
+        }
+        0x7c => {
+          // bar: race every ordering with this branching
+          let strategies: Vec<_> = Strategy::all()
+            .into_iter()
+            .filter(|s| s.branching == strategy.branching)
+            .collect();
+          let options = solve_options(&trace, false);
+          let (outcome, stats) = self.solve_portfolio(&dictionary, &strategies, &options, &mut rng);
+          Some(format!(
+            "{} ({} nodes in {:.0?})",
+            self.report(outcome),
+            stats.nodes,
+            stats.elapsed
+          ))
+        }
+        0x21 => {
+          // exclamation mark: switch between letter and word branching
+          strategy.branching = match strategy.branching {
+            Branching::Letter => Branching::Word,
+            Branching::Word => Branching::Letter,
+          };
+          Some(format!("{:?}", strategy))
+        }
+        0x3f => {
+          // question mark: next variable ordering
+          strategy.variables = match strategy.variables {
+            VariableOrder::Fewest => VariableOrder::FewestThenDegree,
+            VariableOrder::FewestThenDegree => VariableOrder::FailureWeighted,
+            VariableOrder::FailureWeighted => VariableOrder::LongestFirst,
+            VariableOrder::LongestFirst => VariableOrder::Fewest,
+          };
+          Some(format!("{:?}", strategy))
+        }
+        0x24 => {
+          // dollar: switch value ordering
+          strategy.values = match strategy.values {
+            ValueOrder::Sampled => ValueOrder::LeastConstraining,
+            ValueOrder::LeastConstraining => ValueOrder::Sampled,
+          };
+          Some(format!("{:?}", strategy))
+        }
+        0x3a => {
+          // colon: switch what best fills and improvements score
+          objective = match objective {
+            Objective::Minimum => Objective::Total,
+            Objective::Total => Objective::Minimum,
+          };
+          Some(format!("Scoring fills by {:?} entry score", objective))
+        }
+        0x25 => {
+          // percent: time every strategy on this grid
+          let seeds: Vec<u64> = (0..BENCHMARK_SEEDS).map(|_| rng.gen()).collect();
+          let cancel = CancelToken::new();
+          let mut watcher = Interruptible::new(cancel.clone());
+          let timings: Vec<Timing> =
+            self.benchmark(&dictionary, BENCHMARK_TIME, &seeds, &cancel, &mut watcher);
+          match timings.first() {
+            Some(fastest) => Some(format!(
+              "{} {:?} in {:?} (median), solved {} of {}",
+              if cancel.is_cancelled() { "Stopped, fastest so far" } else { "Fastest" },
+              fastest.strategy,
+              fastest.elapsed,
+              fastest.solved,
+              seeds.len()
+            )),
+            None => Some("Stopped before timing any strategy".to_string()),
+          }
+        }
+        0x3e => {
+          // greater than: resume the saved search
+          let state =
+            File::open(CHECKPOINT).and_then(|file| SearchState::load(BufReader::new(file)));
+          let options = solve_options(&trace, step_through);
+          match state {
+            Ok(state) => match self.resume(
+              &dictionary,
+              state,
+              &options,
+              &mut rng,
+              &mut *self.observer(step_through, &options.cancel),
+            ) {
+              Some((outcome, _)) => Some(self.report(outcome)),
+              None => Some("Saved search is for another grid".to_string()),
+            },
+            Err(e) => Some(format!("Can't load {}: {}", CHECKPOINT, e)),
+          }
+        }
+        0x3d => {
+          // equals: estimate fills and solve time
+          let estimate: Option<Estimate> = self.estimate(&dictionary, ETA_PROBES, &mut rng);
+          match estimate {
+            Some(estimate) if estimate.fills() == 0.0 => Some("Probes found no fills".to_string()),
+            Some(estimate) => Some(format!(
+              "About 1e{:.1} fills, ETA {:.0?}",
+              estimate.log10_fills(),
+              estimate.eta()
+            )),
+            None => Some("Impossible!".to_string()),
+          }
+        }
+        0x23 => {
+          // hash: count fills
+          let limit = 100;
+          match self.count_solutions(&dictionary, limit) {
+            n if n < limit => Some(format!("{} fills", n)),
+            n => Some(format!("At least {} fills", n)),
+          }
+        }
+        0x2a => {
+          // asterisk: best fill
+          let options = solve_options(&trace, false);
+          match self.solve_best(&dictionary, letter_score, objective, &options) {
+            (Outcome::Solved, Some(score)) => Some(format!("Best fill scored {}", score)),
+            (Outcome::Stopped(limit), Some(score)) => Some(format!(
+              "Stopped {}, best fill so far scored {}",
+              limit, score
+            )),
+            (outcome, _) => Some(self.report(outcome)),
+          }
+        }
+        0x2c => {
+          // comma: refill, keeping as many solved letters as possible
+          let options = solve_options(&trace, false);
+          match self.refill(&dictionary, &options) {
+            (Outcome::Solved, Some(changes)) => {
+              Some(format!("Refilled, changing {} squares", changes))
+            }
+            (Outcome::Stopped(limit), Some(changes)) => Some(format!(
+              "Stopped {}, best refill changes {} squares",
+              limit, changes
+            )),
+            (outcome, _) => Some(self.report(outcome)),
+          }
+        }
+        0x27 => {
+          // apostrophe: add or remove this square from the region to refill
+          if !region.remove(&(u, v)) && self.squares.contains_key(&(u, v)) {
+            region.insert((u, v));
+          }
+          corner = Some((u, v));
+          Some(format!("Region has {} squares", region.len()))
+        }
+        0x22 => {
+          // double quote: add the rectangle from the last toggled square to here
+          let (cx, cy) = corner.unwrap_or((u, v));
+          for x in cx.min(u)..=cx.max(u) {
+            for y in cy.min(v)..=cy.max(v) {
+              if self.squares.contains_key(&(x, y)) {
+                region.insert((x, y));
+              }
+            }
+          }
+          Some(format!("Region has {} squares", region.len()))
+        }
+        0x5f => {
+          // underscore: refill the region, keeping every other square
+          let options = solve_options(&trace, step_through);
+          let (outcome, stats) = self.refill_region(
+            &dictionary,
+            &region,
+            strategy,
+            &options,
+            &mut rng,
+            &mut *self.observer(step_through, &options.cancel),
+          );
+          region.clear();
+          corner = None;
+          Some(format!(
+            "{} ({} nodes in {:.0?})",
+            self.report(outcome),
+            stats.nodes,
+            stats.elapsed
+          ))
+        }
+        0x7e => {
+          // tilde: fill by local search
+          if self.solve_local(&dictionary, LOCAL_STEPS, &mut rng) {
+            Some("Solved!".to_string())
           } else {
-            Some(format!("Failed!"))
+            Some("Failed!".to_string())
+          }
+        }
+        0x2b => {
+          // plus: improve the current fill
+          match self.improve(
+            &dictionary,
+            letter_score,
+            objective,
+            LOCAL_STEPS,
+            &mut rng,
+          ) {
+            Some(score) => Some(format!("Improved fill scores {}", score)),
+            None => Some("Nothing to improve".to_string()),
+          }
+        }
+        0x2f => {
+          // slash: prefilter
+          match self.prefilter(&dictionary) {
+            Ok(lines) => {
+              let removed: usize = lines.iter().map(|line| line.removed).sum();
+              lines.iter().min_by_key(|line| line.remaining).map(|tightest| {
+                format!(
+                  "Prefilter removed {} candidates, tightest {:?} {} has {}",
+                  removed,
+                  tightest.start,
+                  if tightest.direction == 0 { "across" } else { "down" },
+                  tightest.remaining
+                )
+              })
+            }
+            Err(emptied) => {
+              let emptied: Vec<_> = emptied.iter().map(|entry| entry.to_string()).collect();
+              Some(format!("Impossible! No words for {}", emptied.join(", ")))
+            }
           }
         }
         0x20 => {
@@ -536,6 +2112,9 @@ impl View for Grid {
         }
         c => Some(format!("Unrecognized")),
       };
+      if let Some(trace) = &trace {
+        trace.flush();
+      }
       self.cursor(0, 0);
       if let Some(s) = message {
         addstr(&format!("0x{:x}: {}", input, s));
@@ -562,3 +2141,960 @@ impl View for Grid {
     }
   }
 }
+
+#[cfg(test)]
+mod test_crossword {
+  use super::*;
+
+  fn entries(grid: &Grid) -> Vec<String> {
+    let puzzle = Puzzle::new(grid);
+    puzzle
+      .lines
+      .iter()
+      .map(|line| {
+        line
+          .cell_indices
+          .iter()
+          .map(|ci| match grid.squares[&puzzle.cell_positions[*ci as usize]] {
+            Square::Fixed(ch) | Square::Solved(ch) => ch,
+            Square::Empty => ' ',
+          })
+          .collect()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_solve() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut words = HashSet::new();
+    dictionary.visit_all(|_, s: &str| {
+      words.insert(s.to_string());
+    });
+    let strategies = [
+      Strategy::default(),
+      Strategy {
+        branching: Branching::Word,
+        ..Strategy::default()
+      },
+      Strategy {
+        variables: VariableOrder::FailureWeighted,
+        values: ValueOrder::LeastConstraining,
+        ..Strategy::default()
+      },
+      Strategy {
+        branching: Branching::Word,
+        variables: VariableOrder::LongestFirst,
+        values: ValueOrder::LeastConstraining,
+      },
+    ];
+    for &strategy in &strategies {
+      let mut grid = Grid::new_rectangle(4, 4);
+      assert!(matches!(
+        grid.solve(
+          &dictionary,
+          strategy,
+          &SolveOptions::default(),
+          &mut StdRng::seed_from_u64(1),
+          &mut ()
+        ),
+        (Outcome::Solved, _)
+      ));
+      let entries = entries(&grid);
+      assert!(entries.iter().all(|entry| words.contains(entry)));
+      let distinct: HashSet<_> = entries.iter().collect();
+      assert_eq!(distinct.len(), entries.len());
+    }
+  }
+
+  #[test]
+  fn test_seeded_solve() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let solve = |seed| {
+      let mut grid = Grid::new_rectangle(4, 4);
+      grid.solve(
+        &dictionary,
+        Strategy::default(),
+        &SolveOptions::default(),
+        &mut StdRng::seed_from_u64(seed),
+        &mut (),
+      );
+      entries(&grid)
+    };
+    assert_eq!(solve(7), solve(7));
+    // An empty 4x4 grid has many fills, so a seed which was ignored would
+    // give the same one every time.
+    assert_ne!(solve(7), solve(8));
+  }
+
+  #[test]
+  fn test_status_line() {
+    let status = |eta| {
+      StatusLine::text(&Progress {
+        depth: 2,
+        nodes: 10,
+        backtracks: 3,
+        remaining: 1500.0,
+        eta,
+      })
+    };
+    assert!(status(None).starts_with("\r****1.5e3 Choices, ETA ?, 10 nodes, 3 backtracks"));
+    let eta = Some(Duration::from_secs(3));
+    assert!(status(eta).starts_with("\r****1.5e3 Choices, ETA 3s, 10 nodes"));
+    assert!(status(Some(Duration::MAX)).starts_with("\r****1.5e3 Choices, ETA never"));
+  }
+
+  #[test]
+  fn test_seeded_solve_with_eta() {
+    struct Eta;
+    impl Observer for Eta {
+      fn progress(&mut self, _: &Progress) {}
+      fn wants_eta(&self) -> bool {
+        true
+      }
+    }
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let solve = |observer: &mut dyn Observer| {
+      let mut grid = Grid::new_rectangle(4, 4);
+      grid.solve(
+        &dictionary,
+        Strategy::default(),
+        &SolveOptions::default(),
+        &mut StdRng::seed_from_u64(7),
+        observer,
+      );
+      entries(&grid)
+    };
+    assert_eq!(solve(&mut ()), solve(&mut Eta));
+  }
+
+  #[test]
+  fn test_failure() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut grid = Grid::new_rectangle(3, 3);
+    grid.set_square(1, 1, Square::Fixed('Q'));
+    grid.set_square(2, 1, Square::Fixed('X'));
+    grid.set_square(3, 3, Square::Fixed('E'));
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut fail = |options: &SolveOptions| match grid.solve(
+      &dictionary,
+      Strategy::default(),
+      options,
+      &mut rng,
+      &mut (),
+    ) {
+      (Outcome::Failed(failure), _) => failure,
+      _ => panic!("expected the solve to fail"),
+    };
+    let failure = fail(&SolveOptions::default());
+    assert_eq!(failure.rejected, vec![(2, 1)]);
+    assert!(failure.core.is_empty());
+    let failure = fail(&SolveOptions {
+      explain: true,
+      ..SolveOptions::default()
+    });
+    assert_eq!(failure.rejected, vec![(2, 1)]);
+    assert_eq!(failure.core, vec![(1, 1), (2, 1)]);
+    assert!(failure.core_minimal);
+    let across = Entry {
+      start: (1, 1),
+      direction: 0,
+    };
+    assert!(failure.emptied.contains(&across));
+    assert!(failure.core_entries.contains(&across));
+    assert!(!failure.exhausted);
+  }
+
+  #[test]
+  fn test_prefilter() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let (mut fours, mut qs) = (0, 0);
+    dictionary.visit_all(|_, s: &str| {
+      if s.len() == 4 {
+        fours += 1;
+        if s.starts_with('Q') {
+          qs += 1;
+        }
+      }
+    });
+    let mut grid = Grid::new_rectangle(4, 4);
+    grid.set_square(1, 1, Square::Fixed('Q'));
+    let lines = grid.prefilter(&dictionary).ok().unwrap();
+    assert_eq!(lines.len(), 8);
+    for line in &lines {
+      assert_eq!(line.removed + line.remaining, fours);
+      if line.start == (1, 1) {
+        // The fixed Q alone rules out every word not starting with it.
+        assert!(line.remaining <= qs);
+        assert!(line.removed >= fours - qs);
+      }
+    }
+
+    let mut grid = Grid::new_rectangle(3, 3);
+    grid.set_square(1, 1, Square::Fixed('Q'));
+    grid.set_square(2, 1, Square::Fixed('X'));
+    let emptied = grid.prefilter(&dictionary).err().unwrap();
+    assert!(emptied.contains(&Entry {
+      start: (1, 1),
+      direction: 0,
+    }));
+  }
+
+  #[test]
+  fn test_backjumping() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    // An open 5x5 block, searched first for its longer entries, beside a
+    // 4x4 block with no fill, which nothing chosen in the first one causes.
+    let mut grid = Grid::new_rectangle(10, 5);
+    for y in 1..=5 {
+      grid.squares.remove(&(6, y));
+    }
+    for x in 7..=10 {
+      grid.squares.remove(&(x, 5));
+    }
+    grid.set_square(8, 1, Square::Fixed('G'));
+    grid.set_square(9, 3, Square::Fixed('B'));
+    grid.set_square(7, 4, Square::Fixed('B'));
+    grid.set_square(10, 4, Square::Fixed('S'));
+    let strategy = Strategy {
+      variables: VariableOrder::LongestFirst,
+      ..Strategy::default()
+    };
+    let options = SolveOptions {
+      max_nodes: Some(2000),
+      ..SolveOptions::default()
+    };
+    let solve = |backjumping| {
+      let mut grid = grid.clone();
+      let puzzle = Arc::new(Puzzle::new(&grid));
+      let (solver, _) = grid.prepare(&puzzle, &dictionary).unwrap();
+      let (mut rng, mut observer) = (StdRng::seed_from_u64(1), ());
+      let mut search = Search::new(strategy, &options, &mut rng, &mut observer);
+      search.backjumping = backjumping;
+      grid.run_search(&puzzle, &dictionary, solver, vec![], search)
+    };
+    let (outcome, jumping) = solve(true);
+    assert!(matches!(outcome, Outcome::Failed(_)));
+    let (_, chronological) = solve(false);
+    assert!(jumping.nodes < chronological.nodes);
+  }
+
+  #[test]
+  fn test_resume() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut grid = Grid::new_rectangle(4, 4);
+    let mut rng = StdRng::seed_from_u64(1);
+    let words = Strategy {
+      branching: Branching::Word,
+      ..Strategy::default()
+    };
+    let options = SolveOptions {
+      max_nodes: Some(5),
+      ..SolveOptions::default()
+    };
+    let state = match grid.solve(&dictionary, words, &options, &mut rng, &mut ()) {
+      (Outcome::Paused(state, Limit::Nodes), _) => state,
+      _ => panic!("expected the search to pause"),
+    };
+    let mut saved = vec![];
+    state.save(&mut saved).unwrap();
+    let loaded = SearchState::load(&saved[..]).unwrap();
+    let mut resaved = vec![];
+    loaded.save(&mut resaved).unwrap();
+    assert_eq!(saved, resaved);
+    assert!(matches!(
+      grid.resume(
+        &dictionary,
+        loaded,
+        &SolveOptions::default(),
+        &mut rng,
+        &mut ()
+      ),
+      Some((Outcome::Solved, _))
+    ));
+    // As if it were paused with another dictionary.
+    let mut other = state.clone();
+    other.dictionary ^= 1;
+    let options = SolveOptions::default();
+    assert!(grid.resume(&dictionary, other, &options, &mut rng, &mut ()).is_none());
+    grid.set_square(1, 1, Square::Fixed('Q'));
+    assert!(grid
+      .resume(
+        &dictionary,
+        state,
+        &SolveOptions::default(),
+        &mut rng,
+        &mut ()
+      )
+      .is_none());
+  }
+
+  #[test]
+  fn test_benchmark() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let grid = Grid::new_rectangle(4, 4);
+    let limit = Duration::from_secs(5);
+    let cancel = CancelToken::new();
+    let timings = grid.benchmark(&dictionary, limit, &[1, 2], &cancel, &mut ());
+    assert_eq!(timings.len(), Strategy::all().len());
+    assert!(timings.iter().all(|timing| timing.solved == 2));
+    cancel.cancel();
+    assert!(grid.benchmark(&dictionary, limit, &[1, 2], &cancel, &mut ()).is_empty());
+  }
+
+  #[test]
+  fn test_resume_goes_on_as_before() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let weighted = Strategy {
+      variables: VariableOrder::FailureWeighted,
+      values: ValueOrder::LeastConstraining,
+      ..Strategy::default()
+    };
+    let mut grid = Grid::new_rectangle(6, 6);
+    let mut rng = StdRng::seed_from_u64(1);
+    let options = SolveOptions::default();
+    let (outcome, stats) = grid.solve(&dictionary, weighted, &options, &mut rng, &mut ());
+    assert!(matches!(outcome, Outcome::Solved));
+    let filled = entries(&grid);
+    let mut grid = Grid::new_rectangle(6, 6);
+    let mut rng = StdRng::seed_from_u64(1);
+    let limited = SolveOptions {
+      max_nodes: Some(stats.nodes / 2),
+      ..SolveOptions::default()
+    };
+    let state = match grid.solve(&dictionary, weighted, &limited, &mut rng, &mut ()) {
+      (Outcome::Paused(state, Limit::Nodes), _) => state,
+      _ => panic!("expected the search to pause"),
+    };
+    assert!(state.weights.iter().any(|weight| *weight > 1));
+    let mut saved = vec![];
+    state.save(&mut saved).unwrap();
+    let loaded = SearchState::load(&saved[..]).unwrap();
+    let paused = loaded.nodes();
+    let resumed = grid.resume(&dictionary, loaded, &options, &mut rng, &mut ());
+    let (outcome, rest) = resumed.unwrap();
+    assert!(matches!(outcome, Outcome::Solved));
+    // The same search, with no node visited twice.
+    assert_eq!(paused + rest.nodes, stats.nodes);
+    assert_eq!(entries(&grid), filled);
+  }
+
+  #[test]
+  fn test_restarts() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut words = HashSet::new();
+    dictionary.visit_all(|_, s: &str| {
+      words.insert(s.to_string());
+    });
+    let schedules = [
+      Restarts::Luby { unit: 2 },
+      Restarts::Geometric {
+        first: 2,
+        factor: 1.5,
+      },
+    ];
+    for &restarts in &schedules {
+      let mut grid = Grid::new_rectangle(4, 4);
+      assert!(matches!(
+        grid.solve_restarting(
+          &dictionary,
+          Strategy::default(),
+          restarts,
+          &SolveOptions::default(),
+          &mut StdRng::seed_from_u64(1),
+          &mut ()
+        ),
+        (Outcome::Solved, _)
+      ));
+      assert!(entries(&grid).iter().all(|entry| words.contains(entry)));
+    }
+    // Like `solve`, a failure without `keep_closest` leaves no partial fill.
+    let mut grid = Grid::new_rectangle(4, 4);
+    grid.set_square(2, 1, Square::Fixed('G'));
+    grid.set_square(3, 3, Square::Fixed('B'));
+    grid.set_square(1, 4, Square::Fixed('B'));
+    grid.set_square(4, 4, Square::Fixed('S'));
+    assert!(matches!(
+      grid.solve_restarting(
+        &dictionary,
+        Strategy::default(),
+        schedules[0],
+        &SolveOptions::default(),
+        &mut StdRng::seed_from_u64(1),
+        &mut ()
+      ),
+      (Outcome::Failed(_), _)
+    ));
+    assert!(!grid.squares.values().any(|square| matches!(square, Square::Solved(_))));
+  }
+
+  #[test]
+  fn test_local() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut words = HashSet::new();
+    dictionary.visit_all(|_, s: &str| {
+      words.insert(s.to_string());
+    });
+    let mut grid = Grid::new_rectangle(4, 4);
+    grid.set_square(1, 1, Square::Fixed('S'));
+    let mut rng = StdRng::seed_from_u64(1);
+    assert!(grid.solve_local(&dictionary, 100000, &mut rng));
+    let filled = entries(&grid);
+    assert!(filled.iter().all(|entry| words.contains(entry)));
+    let distinct: HashSet<_> = filled.iter().collect();
+    assert_eq!(distinct.len(), filled.len());
+    assert!(matches!(grid.squares[&(1, 1)], Square::Fixed('S')));
+    let minimum = |grid: &Grid| entries(grid).iter().map(|entry| letter_score(entry)).min();
+    let before = minimum(&grid).unwrap() as u64;
+    let after = grid.improve(
+      &dictionary,
+      letter_score,
+      Objective::Minimum,
+      2000,
+      &mut rng,
+    );
+    assert!(after.unwrap() >= before);
+    assert_eq!(minimum(&grid), after.map(|score| score as u32));
+    assert!(entries(&grid).iter().all(|entry| words.contains(entry)));
+  }
+
+  #[test]
+  fn test_solve_best() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut words = HashSet::new();
+    dictionary.visit_all(|_, s: &str| {
+      words.insert(s.to_string());
+    });
+    let minimum = |grid: &Grid| {
+      let scores = entries(grid).into_iter().map(|entry| letter_score(&entry));
+      scores.min().unwrap() as u64
+    };
+    let mut grid = Grid::new_rectangle(4, 4);
+    grid.solve(
+      &dictionary,
+      Strategy::default(),
+      &SolveOptions::default(),
+      &mut StdRng::seed_from_u64(1),
+      &mut (),
+    );
+    let plain = minimum(&grid);
+    let mut grid = Grid::new_rectangle(4, 4);
+    let options = SolveOptions::default();
+    let (outcome, best) = grid.solve_best(&dictionary, letter_score, Objective::Minimum, &options);
+    assert!(matches!(outcome, Outcome::Solved));
+    let filled = entries(&grid);
+    assert!(filled.iter().all(|entry| words.contains(entry)));
+    let distinct: HashSet<_> = filled.iter().collect();
+    assert_eq!(distinct.len(), filled.len());
+    assert_eq!(best, Some(minimum(&grid)));
+    assert!(best.unwrap() > plain);
+    let limited = SolveOptions {
+      max_nodes: Some(1),
+      ..SolveOptions::default()
+    };
+    let mut grid = Grid::new_rectangle(4, 4);
+    let (outcome, _) = grid.solve_best(&dictionary, letter_score, Objective::Minimum, &limited);
+    assert!(matches!(outcome, Outcome::Stopped(Limit::Nodes)));
+  }
+
+  #[test]
+  fn test_stats() {
+    #[derive(Clone, Default)]
+    struct Shared(Arc<std::sync::Mutex<Vec<u8>>>);
+    impl std::io::Write for Shared {
+      fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+      }
+      fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+      }
+    }
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut grid = Grid::new_rectangle(4, 4);
+    let out = Shared::default();
+    let options = SolveOptions {
+      trace: Some(Trace::new(out.clone())),
+      ..SolveOptions::default()
+    };
+    let (outcome, stats) = grid.solve(
+      &dictionary,
+      Strategy::default(),
+      &options,
+      &mut StdRng::seed_from_u64(1),
+      &mut (),
+    );
+    assert!(matches!(outcome, Outcome::Solved));
+    assert!(stats.nodes > 0 && stats.commits >= stats.nodes - 1);
+    assert!(stats.narrowings > 0 && stats.peak_trail_bytes > 0);
+    assert!(stats.propagation_time <= stats.elapsed);
+    let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+    let events: Vec<_> = out.lines().collect();
+    let count = |event: &str| {
+      let event = format!("{{\"event\":\"{}\"", event);
+      events
+        .iter()
+        .filter(|line| line.starts_with(&event))
+        .count()
+    };
+    assert_eq!(count("commit") + count("fail"), stats.commits);
+    let backtracks: usize = stats.backtracks.iter().sum();
+    assert_eq!(count("fail") + count("backtrack"), backtracks);
+    assert!(events.iter().all(|line| line.ends_with('}')));
+  }
+
+  #[test]
+  fn test_steps() {
+    struct Steps(Vec<Step>);
+    impl Observer for Steps {
+      fn progress(&mut self, _: &Progress) {}
+      fn step(&mut self, step: &Step) {
+        self.0.push(step.clone());
+      }
+      fn wants_steps(&self) -> bool {
+        true
+      }
+    }
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut grid = Grid::new_rectangle(4, 4);
+    grid.set_square(1, 1, Square::Fixed('Q'));
+    let mut observer = Steps(vec![]);
+    let (outcome, stats) = grid.solve(
+      &dictionary,
+      Strategy::default(),
+      &SolveOptions::default(),
+      &mut StdRng::seed_from_u64(1),
+      &mut observer,
+    );
+    assert!(matches!(outcome, Outcome::Solved));
+    let count = |kind| observer.0.iter().filter(|step| step.kind == kind).count();
+    assert_eq!(
+      count(StepKind::Commit) + count(StepKind::Fail),
+      stats.commits
+    );
+    let backtracks: usize = stats.backtracks.iter().sum();
+    assert_eq!(
+      count(StepKind::Fail) + count(StepKind::Backtrack),
+      backtracks
+    );
+    for step in &observer.0 {
+      assert_eq!(step.chosen.len(), 1);
+      let letter = |position| step.letters.iter().find(|(p, _)| *p == position).unwrap().1;
+      assert_eq!(letter((1, 1)), Some('Q'));
+      assert_eq!(
+        letter(step.chosen[0]).is_some(),
+        step.kind == StepKind::Commit
+      );
+    }
+    let last = observer.0.last().unwrap();
+    assert!(last.letters.iter().all(|(_, letter)| letter.is_some()));
+  }
+
+  #[test]
+  fn test_refill() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut words = HashSet::new();
+    dictionary.visit_all(|_, s: &str| {
+      words.insert(s.to_string());
+    });
+    let mut grid = Grid::new_rectangle(4, 4);
+    let mut rng = StdRng::seed_from_u64(1);
+    let solve = |grid: &mut Grid, rng: &mut StdRng| {
+      grid.solve(
+        &dictionary,
+        Strategy::default(),
+        &SolveOptions::default(),
+        rng,
+        &mut (),
+      )
+    };
+    assert!(matches!(solve(&mut grid, &mut rng).0, Outcome::Solved));
+    let before = grid.clone();
+    let options = SolveOptions::default();
+    assert!(matches!(grid.refill(&dictionary, &options), (Outcome::Solved, Some(0))));
+    assert_eq!(entries(&grid), entries(&before));
+    let letter_of = |square: &Square| match square {
+      Square::Fixed(ch) | Square::Solved(ch) => Some(*ch),
+      Square::Empty => None,
+    };
+    let changed = |grid: &Grid| {
+      let squares = before.squares.iter();
+      let changed = squares.filter(|(p, square)| letter_of(&grid.squares[*p]) != letter_of(square));
+      changed.count()
+    };
+    let letter = match before.squares[&(2, 2)] {
+      Square::Solved(ch) => ch,
+      _ => panic!("expected a solved square"),
+    };
+    let other = if letter == 'E' { 'A' } else { 'E' };
+    grid.set_square(2, 2, Square::Fixed(other));
+    let mut fresh = grid.clone();
+    let mut stopped = grid.clone();
+    let limited = SolveOptions {
+      max_nodes: Some(1),
+      ..SolveOptions::default()
+    };
+    let (outcome, _) = stopped.refill(&dictionary, &limited);
+    assert!(matches!(outcome, Outcome::Stopped(Limit::Nodes)));
+    let (outcome, changes) = grid.refill(&dictionary, &options);
+    assert!(matches!(outcome, Outcome::Solved));
+    let changes = changes.unwrap();
+    assert!(entries(&grid).iter().all(|entry| words.contains(entry)));
+    assert!(matches!(grid.squares[&(2, 2)], Square::Fixed(ch) if ch == other));
+    assert!(changes > 0);
+    assert_eq!(changed(&grid), changes + 1);
+    assert!(matches!(solve(&mut fresh, &mut rng).0, Outcome::Solved));
+    assert!(changed(&fresh) >= changed(&grid));
+  }
+
+  #[test]
+  fn test_refill_region() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut words = HashSet::new();
+    dictionary.visit_all(|_, s: &str| {
+      words.insert(s.to_string());
+    });
+    // Two 5x5 blocks, apart.
+    let mut grid = Grid::new_rectangle(11, 5);
+    for y in 1..=5 {
+      grid.squares.remove(&(6, y));
+    }
+    let mut rng = StdRng::seed_from_u64(1);
+    let (outcome, _) = grid.solve(
+      &dictionary,
+      Strategy::default(),
+      &SolveOptions::default(),
+      &mut rng,
+      &mut (),
+    );
+    assert!(matches!(outcome, Outcome::Solved));
+    let before = grid.clone();
+    let region: HashSet<_> = vec![(4, 4), (5, 4), (4, 5), (5, 5)].into_iter().collect();
+    let refill = |grid: &mut Grid, options: &SolveOptions| {
+      let mut rng = StdRng::seed_from_u64(2);
+      grid.refill_region(&dictionary, &region, Strategy::default(), options, &mut rng, &mut ())
+    };
+    // The search only sees the region's block, so the other block costs
+    // nothing.
+    let mut block = grid.clone();
+    block.squares.retain(|(x, _), _| *x < 6);
+    let (outcome, block_stats) = refill(&mut block, &SolveOptions::default());
+    assert!(matches!(outcome, Outcome::Solved));
+    let (outcome, stats) = refill(&mut grid, &SolveOptions::default());
+    assert!(matches!(outcome, Outcome::Solved));
+    assert_eq!(stats.nodes, block_stats.nodes);
+    assert_eq!(stats.backtracks, block_stats.backtracks);
+    for position in &region {
+      match (&grid.squares[position], &block.squares[position]) {
+        (Square::Solved(a), Square::Solved(b)) => assert_eq!(a, b),
+        _ => panic!("expected the region to be solved"),
+      }
+    }
+    let filled = entries(&grid);
+    assert!(filled.iter().all(|entry| words.contains(entry)));
+    let distinct: HashSet<_> = filled.iter().collect();
+    assert_eq!(distinct.len(), filled.len());
+    for (position, square) in &before.squares {
+      match (square, &grid.squares[position]) {
+        (Square::Solved(a), Square::Solved(b)) if !region.contains(position) => assert_eq!(a, b),
+        (_, Square::Solved(_)) => assert!(region.contains(position)),
+        _ => panic!("expected every square to be filled"),
+      }
+    }
+
+    // A refill which fails or is stopped leaves the grid as it was.
+    let refilled = grid.clone();
+    let limited = SolveOptions {
+      max_nodes: Some(0),
+      ..SolveOptions::default()
+    };
+    let (outcome, _) = refill(&mut grid, &limited);
+    assert!(matches!(outcome, Outcome::Stopped(Limit::Nodes)));
+    assert_eq!(entries(&grid), entries(&refilled));
+    for x in 1..=3 {
+      grid.set_square(x, 4, Square::Fixed('X'));
+    }
+    let failing = grid.clone();
+    let closest = SolveOptions {
+      keep_closest: Some(Closeness::SettledEntries),
+      ..SolveOptions::default()
+    };
+    let (outcome, _) = refill(&mut grid, &closest);
+    assert!(matches!(outcome, Outcome::Failed(_)));
+    assert_eq!(entries(&grid), entries(&failing));
+  }
+
+  #[test]
+  fn test_refill_region_keeps_crossings() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut grid = Grid::new_rectangle(5, 5);
+    let mut rng = StdRng::seed_from_u64(3);
+    let (outcome, _) = grid.solve(
+      &dictionary,
+      Strategy::default(),
+      &SolveOptions::default(),
+      &mut rng,
+      &mut (),
+    );
+    assert!(matches!(outcome, Outcome::Solved));
+    // An empty square next to the region, whose down entry is otherwise
+    // outside it.
+    grid.set_square(3, 5, Square::Empty);
+    let region: HashSet<_> = vec![(4, 4), (5, 4), (4, 5), (5, 5)].into_iter().collect();
+    let (outcome, _) = grid.refill_region(
+      &dictionary,
+      &region,
+      Strategy::default(),
+      &SolveOptions::default(),
+      &mut rng,
+      &mut (),
+    );
+    assert!(matches!(outcome, Outcome::Solved));
+    assert!(matches!(grid.squares[&(3, 5)], Square::Empty));
+    // The empty square can still be filled without changing any other.
+    for square in grid.squares.values_mut() {
+      if let Square::Solved(ch) = *square {
+        *square = Square::Fixed(ch);
+      }
+    }
+    assert_eq!(grid.count_solutions(&dictionary, 1), 1);
+  }
+
+  #[test]
+  fn test_refill_region_reserves_settled_entries() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    // Two 2x2 blocks, apart, the second one settled by its own fixed squares
+    // to the words of the first.
+    let mut grid = Grid::new_rectangle(5, 2);
+    for y in 1..=2 {
+      grid.squares.remove(&(3, y));
+    }
+    for (i, ch) in "AMNO".chars().enumerate() {
+      grid.set_square(i % 2 + 1, i / 2 + 1, Square::Fixed(ch));
+      grid.set_square(i % 2 + 4, i / 2 + 1, Square::Fixed(ch));
+    }
+    let before = grid.clone();
+    let region: HashSet<_> = vec![(4, 1), (5, 1), (4, 2), (5, 2)].into_iter().collect();
+    let (outcome, _) = grid.refill_region(
+      &dictionary,
+      &region,
+      Strategy::default(),
+      &SolveOptions::default(),
+      &mut StdRng::seed_from_u64(1),
+      &mut (),
+    );
+    assert!(matches!(outcome, Outcome::Failed(_)));
+    assert_eq!(entries(&grid), entries(&before));
+  }
+
+  #[test]
+  fn test_solutions() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut words = HashSet::new();
+    dictionary.visit_all(|_, s: &str| {
+      words.insert(s.to_string());
+    });
+    let mut grid = Grid::new_rectangle(4, 4);
+    for (x, ch) in "QUIZ".chars().enumerate() {
+      grid.set_square(x + 1, 1, Square::Fixed(ch));
+    }
+    let fills: Vec<Fill> = grid.solutions(&dictionary).collect();
+    assert!(fills.len() > 2);
+    let mut distinct = HashSet::new();
+    for fill in &fills {
+      let mut filled = grid.clone();
+      for (&(x, y), &ch) in fill {
+        if let Square::Empty = filled.squares[&(x, y)] {
+          filled.set_square(x, y, Square::Solved(ch));
+        }
+      }
+      let entries = entries(&filled);
+      assert!(entries.iter().all(|entry| words.contains(entry)));
+      assert_eq!(entries.iter().collect::<HashSet<_>>().len(), entries.len());
+      for (x, ch) in "QUIZ".chars().enumerate() {
+        assert_eq!(fill.get(&(x + 1, 1)).copied().unwrap_or(ch), ch);
+      }
+      let mut letters: Vec<_> = fill.iter().map(|(p, ch)| (*p, *ch)).collect();
+      letters.sort_unstable();
+      assert!(distinct.insert(letters));
+    }
+    assert_eq!(grid.count_solutions(&dictionary, fills.len() + 1), fills.len());
+    let limit = fills.len() / 2;
+    assert_eq!(grid.count_solutions(&dictionary, limit), limit);
+  }
+
+  #[test]
+  fn test_estimate() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut grid = Grid::new_rectangle(4, 4);
+    for (x, ch) in "QUIZ".chars().enumerate() {
+      grid.set_square(x + 1, 1, Square::Fixed(ch));
+    }
+    let mut rng = StdRng::seed_from_u64(1);
+    let fills = grid.count_solutions(&dictionary, 1000) as f64;
+    let estimate = grid.estimate(&dictionary, 200, &mut rng).unwrap();
+    assert!(estimate.fills() > fills / 2.0 && estimate.fills() < fills * 2.0);
+
+    struct Eta(Vec<Option<Duration>>);
+    impl Observer for Eta {
+      fn progress(&mut self, progress: &Progress) {
+        self.0.push(progress.eta);
+      }
+      fn wants_eta(&self) -> bool {
+        true
+      }
+    }
+    let mut observer = Eta(vec![]);
+    let (outcome, _) = grid.solve(
+      &dictionary,
+      Strategy::default(),
+      &SolveOptions::default(),
+      &mut rng,
+      &mut observer,
+    );
+    assert!(matches!(outcome, Outcome::Solved));
+    assert!(!observer.0.is_empty() && observer.0.iter().all(|eta| eta.is_some()));
+  }
+
+  #[test]
+  fn test_parallel() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut words = HashSet::new();
+    dictionary.visit_all(|_, s: &str| {
+      words.insert(s.to_string());
+    });
+    let mut rng = StdRng::seed_from_u64(1);
+    let options = SolveOptions::default();
+    let mut grid = Grid::new_rectangle(4, 4);
+    let (outcome, stats) =
+      grid.solve_parallel(&dictionary, Strategy::default(), 4, &options, &mut rng);
+    assert!(matches!(outcome, Outcome::Solved));
+    assert!(stats.nodes > 0 && stats.commits > 0);
+    assert!(entries(&grid).iter().all(|entry| words.contains(entry)));
+    let mut grid = Grid::new_rectangle(4, 4);
+    let (outcome, stats) = grid.solve_portfolio(&dictionary, &Strategy::all(), &options, &mut rng);
+    assert!(matches!(outcome, Outcome::Solved));
+    assert!(stats.nodes > 0);
+    assert!(entries(&grid).iter().all(|entry| words.contains(entry)));
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let cancelled = SolveOptions {
+      cancel: Some(cancel),
+      ..SolveOptions::default()
+    };
+    let mut grid = Grid::new_rectangle(4, 4);
+    let outcome = grid.solve_parallel(&dictionary, Strategy::default(), 4, &cancelled, &mut rng);
+    assert!(matches!(outcome, (Outcome::Stopped(Limit::Cancelled), _)));
+    let outcome = grid.solve_portfolio(&dictionary, &Strategy::all(), &cancelled, &mut rng);
+    assert!(matches!(outcome, (Outcome::Stopped(Limit::Cancelled), _)));
+    // Propagation alone doesn't rule this one out, so every worker has to
+    // run out of subtrees.
+    grid = Grid::new_rectangle(4, 4);
+    grid.set_square(2, 1, Square::Fixed('G'));
+    grid.set_square(3, 3, Square::Fixed('B'));
+    grid.set_square(1, 4, Square::Fixed('B'));
+    grid.set_square(4, 4, Square::Fixed('S'));
+    assert!(grid.prefilter(&dictionary).is_ok());
+    let outcome = grid.solve_parallel(&dictionary, Strategy::default(), 4, &options, &mut rng);
+    assert!(matches!(outcome, (Outcome::Failed(_), _)));
+    // Shrinking its core takes searches, which stop with the solve's options.
+    let puzzle = Arc::new(Puzzle::new(&grid));
+    let explain = SolveOptions {
+      explain: true,
+      ..cancelled
+    };
+    let failure = grid.explain(&puzzle, &dictionary, true, &explain);
+    assert!(failure.core.is_empty());
+    assert!(!failure.core_minimal);
+  }
+
+  #[test]
+  fn test_limits() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut grid = Grid::new_rectangle(4, 4);
+    let mut rng = StdRng::seed_from_u64(1);
+    let limited = |options: SolveOptions, grid: &mut Grid, rng: &mut StdRng| match grid.solve(
+      &dictionary,
+      Strategy::default(),
+      &options,
+      rng,
+      &mut (),
+    ) {
+      (Outcome::Paused(_, limit), _) => Some(limit),
+      _ => None,
+    };
+    let token = CancelToken::new();
+    token.cancel();
+    let cancelled = SolveOptions {
+      cancel: Some(token),
+      ..SolveOptions::default()
+    };
+    assert_eq!(
+      limited(cancelled, &mut grid, &mut rng),
+      Some(Limit::Cancelled)
+    );
+    let expired = SolveOptions::within(Duration::from_secs(0));
+    assert_eq!(limited(expired, &mut grid, &mut rng), Some(Limit::Deadline));
+    let nodes = SolveOptions {
+      max_nodes: Some(3),
+      ..SolveOptions::default()
+    };
+    assert_eq!(limited(nodes, &mut grid, &mut rng), Some(Limit::Nodes));
+    let generous = SolveOptions {
+      max_nodes: Some(1000000),
+      ..SolveOptions::within(Duration::from_secs(600))
+    };
+    assert_eq!(limited(generous, &mut grid, &mut rng), None);
+    assert!(entries(&grid).iter().all(|entry| !entry.contains(' ')));
+  }
+
+  #[test]
+  fn test_keep_closest() {
+    let dictionary = english_scrabble_dict().ok().unwrap();
+    let mut grid = Grid::new_rectangle(4, 4);
+    grid.set_square(2, 1, Square::Fixed('G'));
+    grid.set_square(3, 3, Square::Fixed('B'));
+    grid.set_square(1, 4, Square::Fixed('B'));
+    grid.set_square(4, 4, Square::Fixed('S'));
+    let solved = |grid: &Grid| {
+      let squares = grid.squares.values();
+      squares
+        .filter(|square| matches!(square, Square::Solved(_)))
+        .count()
+    };
+    let mut rng = StdRng::seed_from_u64(1);
+    let options = SolveOptions::default();
+    let (outcome, _) = grid.solve(
+      &dictionary,
+      Strategy::default(),
+      &options,
+      &mut rng,
+      &mut (),
+    );
+    assert!(matches!(outcome, Outcome::Failed(_)));
+    assert_eq!(solved(&grid), 0);
+    for &closeness in &[Closeness::FilledSquares, Closeness::SettledEntries] {
+      let options = SolveOptions {
+        keep_closest: Some(closeness),
+        ..SolveOptions::default()
+      };
+      let (outcome, _) = grid.solve(
+        &dictionary,
+        Strategy::default(),
+        &options,
+        &mut rng,
+        &mut (),
+      );
+      assert!(matches!(outcome, Outcome::Failed(_)));
+      assert!(solved(&grid) > 0);
+      // The partial fill is one the search reached, so propagation alone
+      // can't rule it out.
+      let mut partial = grid.clone();
+      for square in partial.squares.values_mut() {
+        if let Square::Solved(ch) = *square {
+          *square = Square::Fixed(ch);
+        }
+      }
+      assert!(partial.prefilter(&dictionary).is_ok());
+    }
+  }
+}
@@ -0,0 +1,39 @@
+use super::{Search, Solver};
+use rand::Rng;
+
+/// How close a partial fill came to a complete one, for keeping the closest
+/// a solve came across.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Closeness {
+  // The most entries down to one word.
+  SettledEntries,
+  // The fewest squares without a letter.
+  FilledSquares,
+}
+
+impl Solver {
+  fn closeness(&self, closeness: Closeness) -> usize {
+    match closeness {
+      Closeness::SettledEntries => {
+        let lines = self.line_states.iter();
+        lines.filter(|line| line.word_count() == 1).count()
+      }
+      Closeness::FilledSquares => (0..self.puzzle.cells.len())
+        .filter(|ci| self.cell_set(*ci).len() == 1)
+        .count(),
+    }
+  }
+}
+
+impl<R: Rng> Search<'_, R> {
+  /// Keeps the solver's settled squares if they're the closest to a fill so
+  /// far.
+  pub(super) fn remember(&mut self, solver: &Solver) {
+    if let Some(closeness) = self.options.keep_closest {
+      let score = solver.closeness(closeness);
+      if !matches!(&self.closest, Some((best, _)) if *best >= score) {
+        self.closest = Some((score, solver.settled()));
+      }
+    }
+  }
+}
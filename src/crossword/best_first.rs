@@ -0,0 +1,175 @@
+use super::{
+  ord_char, Branch, Grid, Limit, Outcome, Puzzle, Search, SolveOptions, Solver, Strategy,
+  VariableOrder,
+};
+use priority_queue::PriorityQueue;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::Arc;
+use words::dictionary::Dictionary;
+
+/// How `Grid::solve_best` rates a fill from the scores of its entries.
+#[derive(Clone, Copy, Debug)]
+pub enum Objective {
+  Total,
+  Minimum,
+}
+
+/// A word score for dictionaries which don't come with one: the percentage of
+/// the word's letters which are among the ten most common in English, as
+/// those leave the crossing entries the most room.
+pub fn letter_score(word: &str) -> u32 {
+  let common = word
+    .chars()
+    .filter(|c| "ETAOINSRHL".contains(c.to_ascii_uppercase()))
+    .count();
+  (100 * common / word.len().max(1)) as u32
+}
+
+type Scored = Option<(u64, Vec<(usize, char)>)>;
+
+fn beats(bound: u64, best: &Scored) -> bool {
+  match best {
+    Some((score, _)) => bound > *score,
+    None => true,
+  }
+}
+
+impl Solver {
+  /// Scores every candidate word of the puzzle's line lengths.
+  pub(super) fn score_words<S: Fn(&str) -> u32>(&mut self, score: S) {
+    for line in &self.line_states {
+      let words = &line.words;
+      self.scores.entry(words.length).or_insert_with(|| {
+        (0..words.word_count() as u32)
+          .map(|id| {
+            let word: String = words.word(id).iter().map(|o| ord_char(*o)).collect();
+            score(&word)
+          })
+          .collect()
+      });
+    }
+  }
+
+  /// The best score any remaining candidate could give line `li`.
+  fn line_bound(&self, li: usize) -> u64 {
+    let line = &self.line_states[li];
+    let scores = &self.scores[&line.length()];
+    line.ids().map(|id| scores[id as usize] as u64).max().unwrap_or(0)
+  }
+
+  /// An optimistic bound on the objective of any fill below this state. Once
+  /// every line is down to one word, it is the fill's actual score.
+  fn bound(&self, objective: Objective) -> u64 {
+    let bounds = (0..self.line_states.len()).map(|li| self.line_bound(li));
+    match objective {
+      Objective::Total => bounds.sum(),
+      Objective::Minimum => bounds.min().unwrap_or(0),
+    }
+  }
+
+  /// Undoes back to the root and reapplies a path of choices.
+  fn replay(&mut self, decisions: &[(usize, u8)]) -> Option<()> {
+    self.undo(0);
+    for &(ci, o) in decisions {
+      self.commit_ord(ci, o)?;
+    }
+    Some(())
+  }
+
+  /// Best-first search over partial fills, kept in a priority queue by their
+  /// bound. Partial fills which can't beat the best complete fill so far are
+  /// pruned, and the search stops once none can. Each partial fill expanded
+  /// counts as a node against the limits of `search`. Leaves the best fill
+  /// found in `best`, and returns the limit which stopped the search, if one
+  /// did.
+  fn solve_best<R: Rng>(
+    &mut self,
+    objective: Objective,
+    search: &mut Search<R>,
+    best: &mut Scored,
+  ) -> Result<(), Limit> {
+    let mut queue = PriorityQueue::new();
+    // Deeper partial fills win ties, so that the search reaches complete ones.
+    queue.push(vec![], (self.bound(objective), 0));
+    while let Some((decisions, (bound, depth))) = queue.pop() {
+      if !beats(bound, best) {
+        break;
+      }
+      if let Some(limit) = search.limit_reached() {
+        return Err(limit);
+      }
+      search.nodes += 1;
+      if self.replay(&decisions).is_none() {
+        continue;
+      }
+      let (ci, set) = match self.pick_cell(VariableOrder::Fewest) {
+        Branch::DeadEnd => continue,
+        Branch::Complete => {
+          *best = Some((bound, self.solution()));
+          continue;
+        }
+        Branch::At((ci, set)) => (ci, set),
+      };
+      for o in set.indices() {
+        let mark = self.trail.len();
+        if self.commit_ord(ci, o).is_some() {
+          let bound = self.bound(objective);
+          if beats(bound, best) {
+            match self.pick_cell(VariableOrder::Fewest) {
+              Branch::DeadEnd => {}
+              Branch::Complete => *best = Some((bound, self.solution())),
+              Branch::At(_) => {
+                let mut child = decisions.clone();
+                child.push((ci, o));
+                queue.push(child, (bound, depth + 1));
+              }
+            }
+          }
+        }
+        self.undo(mark);
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Grid {
+  /// Fills the grid with the best fill, rating each entry with `score` and
+  /// the fill by `objective`. If a limit of `options` stops the search first,
+  /// the outcome is `Stopped` and the fill is the best found by then. Returns
+  /// the fill's score; with no fill, the grid is left as it was.
+  pub fn solve_best<S: Fn(&str) -> u32>(
+    &mut self,
+    dictionary: &Dictionary,
+    score: S,
+    objective: Objective,
+    options: &SolveOptions,
+  ) -> (Outcome, Option<u64>) {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let mut best = None;
+    let prepared = self.prepare(&puzzle, dictionary);
+    let exhausted = prepared.is_some();
+    let result = match prepared {
+      Some((mut solver, _)) => {
+        solver.score_words(score);
+        // The search expands partial fills by their bound, so it has no use
+        // for randomness.
+        let (mut rng, mut observer) = (StdRng::seed_from_u64(0), ());
+        let mut search = Search::new(Strategy::default(), options, &mut rng, &mut observer);
+        solver.solve_best(objective, &mut search, &mut best)
+      }
+      None => Ok(()),
+    };
+    let total = best.map(|(total, chars)| {
+      self.clear_solved();
+      self.write_solved(&puzzle, &chars);
+      total
+    });
+    let outcome = match (result, total) {
+      (Err(limit), _) => Outcome::Stopped(limit),
+      (Ok(()), Some(_)) => Outcome::Solved,
+      (Ok(()), None) => Outcome::Failed(self.explain(&puzzle, dictionary, exhausted, options)),
+    };
+    (outcome, total)
+  }
+}
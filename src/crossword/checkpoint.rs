@@ -0,0 +1,353 @@
+use super::{
+  Branching, Choice, Frame, Grid, Levels, Observer, Outcome, Puzzle, Search, SolveOptions,
+  SolveStats, Solver, Square, Strategy, ValueOrder, VariableOrder,
+};
+use rand::Rng;
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+use words::dictionary::Dictionary;
+
+const HEADER: &str = "crossword-search 5";
+
+const BRANCHINGS: [(Branching, &str); 2] =
+  [(Branching::Letter, "letter"), (Branching::Word, "word")];
+const VARIABLE_ORDERS: [(VariableOrder, &str); 4] = [
+  (VariableOrder::Fewest, "fewest"),
+  (VariableOrder::FewestThenDegree, "degree"),
+  (VariableOrder::FailureWeighted, "weighted"),
+  (VariableOrder::LongestFirst, "longest"),
+];
+const VALUE_ORDERS: [(ValueOrder, &str); 2] = [
+  (ValueOrder::Sampled, "sampled"),
+  (ValueOrder::LeastConstraining, "least-constraining"),
+];
+
+/// A depth-first solve which a limit stopped. It can be resumed with new
+/// limits by `Grid::resume`, or saved to disk and loaded again later. Only
+/// the grid and dictionary it was paused on can resume it. The failure
+/// weights are saved, but not the random generator: a `Sampled` search draws
+/// from the generator passed to `resume`, so it only goes on as it would
+/// have without the pause if that generator carries on from the solve's.
+#[derive(Clone, Debug)]
+pub struct SearchState {
+  pub(super) cells: Vec<(usize, usize)>,
+  pub(super) fixed: Vec<((usize, usize), char)>,
+  // A hash of the dictionary's words of the grid's entry lengths.
+  pub(super) dictionary: u64,
+  pub(super) strategy: Strategy,
+  pub(super) frames: Vec<Frame>,
+  pub(super) nogoods: Vec<Vec<Choice>>,
+  // The failure weight of each line, for `VariableOrder::FailureWeighted`.
+  pub(super) weights: Vec<u32>,
+  pub(super) nodes: usize,
+  pub(super) backtracks: usize,
+}
+
+impl SearchState {
+  /// How many nodes the search visited before it was paused.
+  pub fn nodes(&self) -> usize {
+    self.nodes
+  }
+
+  /// Writes the state as lines of text.
+  pub fn save<W: Write>(&self, out: &mut W) -> io::Result<()> {
+    writeln!(out, "{}", HEADER)?;
+    let cells = self.cells.iter().map(|(x, y)| format!("{},{}", x, y));
+    writeln!(out, "cells {}", words(cells))?;
+    let fixed = self
+      .fixed
+      .iter()
+      .map(|((x, y), ch)| format!("{},{},{}", x, y, ch));
+    writeln!(out, "fixed {}", words(fixed))?;
+    writeln!(out, "dictionary {:016x}", self.dictionary)?;
+    writeln!(
+      out,
+      "strategy {} {} {}",
+      name(&BRANCHINGS, self.strategy.branching),
+      name(&VARIABLE_ORDERS, self.strategy.variables),
+      name(&VALUE_ORDERS, self.strategy.values),
+    )?;
+    writeln!(out, "counts {} {}", self.nodes, self.backtracks)?;
+    writeln!(out, "weights {}", list(self.weights.iter()))?;
+    for frame in &self.frames {
+      writeln!(
+        out,
+        "frame {} {} {}",
+        list(frame.chosen.iter().map(|(choice, _)| show_choice(*choice))),
+        list(frame.untried.iter().map(|choice| show_choice(*choice))),
+        list(frame.conflicts.iter()),
+      )?;
+    }
+    for nogood in &self.nogoods {
+      writeln!(
+        out,
+        "nogood {}",
+        list(nogood.iter().map(|choice| show_choice(*choice)))
+      )?;
+    }
+    Ok(())
+  }
+
+  /// Reads a state written by `save`.
+  pub fn load<R: BufRead>(input: R) -> io::Result<SearchState> {
+    let mut lines = input.lines();
+    if lines.next().transpose()?.as_deref() != Some(HEADER) {
+      return Err(invalid("not a saved search"));
+    }
+    let mut state = SearchState {
+      cells: vec![],
+      fixed: vec![],
+      dictionary: 0,
+      strategy: Strategy::default(),
+      frames: vec![],
+      nogoods: vec![],
+      weights: vec![],
+      nodes: 0,
+      backtracks: 0,
+    };
+    for line in lines {
+      let line = line?;
+      let mut fields = line.split_whitespace();
+      match fields.next() {
+        Some("cells") => {
+          for field in fields.filter(|f| *f != "-") {
+            let mut parts = field.split(',');
+            state
+              .cells
+              .push((number(parts.next())?, number(parts.next())?));
+          }
+        }
+        Some("fixed") => {
+          for field in fields.filter(|f| *f != "-") {
+            let mut parts = field.split(',');
+            let position = (number(parts.next())?, number(parts.next())?);
+            match parts.next().and_then(|s| s.chars().next()) {
+              Some(ch) => state.fixed.push((position, ch)),
+              None => return Err(invalid("bad fixed square")),
+            }
+          }
+        }
+        Some("dictionary") => match fields.next().map(|s| u64::from_str_radix(s, 16)) {
+          Some(Ok(hash)) => state.dictionary = hash,
+          _ => return Err(invalid("bad dictionary hash")),
+        },
+        Some("strategy") => {
+          state.strategy = Strategy {
+            branching: named(&BRANCHINGS, fields.next())?,
+            variables: named(&VARIABLE_ORDERS, fields.next())?,
+            values: named(&VALUE_ORDERS, fields.next())?,
+          };
+        }
+        Some("counts") => {
+          state.nodes = number(fields.next())?;
+          state.backtracks = number(fields.next())?;
+        }
+        Some("weights") => {
+          let weights = parse_list(fields.next(), |s| number(Some(s)))?;
+          state.weights = weights.into_iter().map(|weight| weight as u32).collect();
+        }
+        Some("frame") => {
+          let chosen = parse_list(fields.next(), parse_choice)?;
+          let untried = parse_list(fields.next(), parse_choice)?;
+          let mut conflicts = Levels::default();
+          for level in parse_list(fields.next(), |s| number(Some(s)))? {
+            conflicts.insert(level);
+          }
+          state.frames.push(Frame {
+            chosen: chosen.first().map(|choice| (*choice, 0)),
+            untried,
+            conflicts,
+          });
+        }
+        Some("nogood") => state.nogoods.push(parse_list(fields.next(), parse_choice)?),
+        None => {}
+        Some(_) => return Err(invalid("unknown line")),
+      }
+    }
+    Ok(state)
+  }
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Space separated words, or "-" if there are none.
+fn words<I: Iterator<Item = String>>(items: I) -> String {
+  let joined: Vec<_> = items.collect();
+  if joined.is_empty() {
+    "-".to_string()
+  } else {
+    joined.join(" ")
+  }
+}
+
+/// A comma separated list, or "-" if it's empty.
+fn list<T: ToString, I: Iterator<Item = T>>(items: I) -> String {
+  let joined: Vec<_> = items.map(|item| item.to_string()).collect();
+  if joined.is_empty() {
+    "-".to_string()
+  } else {
+    joined.join(",")
+  }
+}
+
+fn parse_list<T, F: Fn(&str) -> io::Result<T>>(
+  field: Option<&str>,
+  parse: F,
+) -> io::Result<Vec<T>> {
+  match field {
+    Some("-") => Ok(vec![]),
+    Some(field) => field.split(',').map(parse).collect(),
+    None => Err(invalid("missing field")),
+  }
+}
+
+fn number(field: Option<&str>) -> io::Result<usize> {
+  match field.map(str::parse) {
+    Some(Ok(n)) => Ok(n),
+    _ => Err(invalid("bad number")),
+  }
+}
+
+fn name<T: PartialEq>(names: &[(T, &'static str)], value: T) -> &'static str {
+  names.iter().find(|(v, _)| *v == value).unwrap().1
+}
+
+fn named<T: Copy>(names: &[(T, &str)], field: Option<&str>) -> io::Result<T> {
+  match names.iter().find(|(_, name)| Some(*name) == field) {
+    Some((value, _)) => Ok(*value),
+    None => Err(invalid("unknown name")),
+  }
+}
+
+/// A choice as "l<cell>:<letter>" or "w<line>:<word>".
+fn show_choice(choice: Choice) -> String {
+  match choice {
+    Choice::Letter(ci, o) => format!("l{}:{}", ci, o),
+    Choice::Word(li, id) => format!("w{}:{}", li, id),
+  }
+}
+
+fn parse_choice(field: &str) -> io::Result<Choice> {
+  let mut parts = field.get(1..).unwrap_or("").split(':');
+  let (target, value) = (number(parts.next())?, number(parts.next())?);
+  match field.chars().next() {
+    Some('l') if value < 26 => Ok(Choice::Letter(target, value as u8)),
+    Some('w') => Ok(Choice::Word(target, value as u32)),
+    _ => Err(invalid("bad choice")),
+  }
+}
+
+impl Solver {
+  /// An FNV-1a hash of the words the solver's lines draw from, so a search
+  /// isn't resumed against a different dictionary.
+  fn fingerprint(&self) -> u64 {
+    let mut indexes: Vec<_> = self.line_states.iter().map(|line| &line.words).collect();
+    indexes.sort_by_key(|words| words.length);
+    indexes.dedup_by_key(|words| words.length);
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for words in indexes {
+      let length = words.length as u8;
+      for &byte in std::iter::once(&length).chain(&words.ords) {
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+      }
+    }
+    hash
+  }
+
+  /// Whether `choice` names a cell or line of this puzzle, and a letter or
+  /// word which could go there.
+  fn can_make(&self, choice: Choice) -> bool {
+    match choice {
+      Choice::Letter(ci, _) => ci < self.puzzle.cells.len(),
+      Choice::Word(li, id) => match self.line_states.get(li) {
+        Some(line) => (id as usize) < line.words.word_count(),
+        None => false,
+      },
+    }
+  }
+}
+
+impl Grid {
+  fn fixed_squares(&self, puzzle: &Puzzle) -> Vec<((usize, usize), char)> {
+    puzzle
+      .cell_positions
+      .iter()
+      .filter_map(|position| match self.squares[position] {
+        Square::Fixed(ch) => Some((*position, ch)),
+        _ => None,
+      })
+      .collect()
+  }
+
+  pub(super) fn paused<R: Rng>(
+    &self,
+    puzzle: &Puzzle,
+    search: &Search<R>,
+    solver: &mut Solver,
+    frames: Vec<Frame>,
+  ) -> SearchState {
+    SearchState {
+      cells: puzzle.cell_positions.clone(),
+      fixed: self.fixed_squares(puzzle),
+      dictionary: solver.fingerprint(),
+      strategy: search.strategy,
+      frames,
+      nogoods: std::mem::take(&mut solver.nogoods),
+      weights: solver.weights.clone(),
+      nodes: search.nodes,
+      backtracks: search.backtracks,
+    }
+  }
+
+  /// Carries on a paused solve within the limits of `options`, branching as
+  /// it did before. Returns None if the state doesn't belong to this grid, as its
+  /// squares, fixed letters or dictionary have changed since. The stats only
+  /// cover the resumed part of the solve.
+  pub fn resume<R: Rng>(
+    &mut self,
+    dictionary: &Dictionary,
+    state: SearchState,
+    options: &SolveOptions,
+    rng: &mut R,
+    observer: &mut dyn Observer,
+  ) -> Option<(Outcome, SolveStats)> {
+    let puzzle = Arc::new(Puzzle::new(self));
+    if state.cells != puzzle.cell_positions || state.fixed != self.fixed_squares(&puzzle) {
+      return None;
+    }
+    let (mut solver, _) = match self.prepare(&puzzle, dictionary) {
+      Some(prepared) => prepared,
+      None => {
+        let failure = self.explain(&puzzle, dictionary, false, options);
+        return Some((Outcome::Failed(failure), SolveStats::default()));
+      }
+    };
+    if solver.fingerprint() != state.dictionary {
+      return None;
+    }
+    let frame_choices = state.frames.iter().flat_map(|frame| {
+      let chosen = frame.chosen.iter().map(|(choice, _)| choice);
+      chosen.chain(frame.untried.iter())
+    });
+    let mut choices = frame_choices.chain(state.nogoods.iter().flatten());
+    if !choices.all(|choice| solver.can_make(*choice)) {
+      return None;
+    }
+    if state.weights.len() != solver.weights.len() {
+      return None;
+    }
+    solver.weights = state.weights;
+    for nogood in state.nogoods {
+      solver.add_nogood(nogood);
+    }
+    let mut frames = state.frames;
+    solver.replay_frames(&mut frames)?;
+    let mut search = Search::new(state.strategy, options, rng, observer);
+    search.nodes = state.nodes;
+    search.first_node = state.nodes;
+    search.backtracks = state.backtracks;
+    Some(self.run_search(&puzzle, dictionary, solver, frames, search))
+  }
+}
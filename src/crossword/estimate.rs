@@ -0,0 +1,121 @@
+use super::{Branch, Grid, Puzzle, Solver, VariableOrder};
+use rand::{seq::SliceRandom, Rng};
+use std::f64::consts::LN_10;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use words::dictionary::Dictionary;
+
+/// What random probes down a letter-branching search tree suggest about it,
+/// by Knuth's estimator. Counts are kept as natural logs, since they soon
+/// outgrow an `f64`.
+#[derive(Clone, Copy, Debug)]
+pub struct Estimate {
+  // Log of the expected number of fills below the node probed from.
+  pub log_fills: f64,
+  // Log of the expected number of letters tried searching the whole tree.
+  pub log_cost: f64,
+  // How long trying one letter took during the probes.
+  pub letter_time: Duration,
+}
+
+impl Estimate {
+  pub fn fills(&self) -> f64 {
+    self.log_fills.exp()
+  }
+
+  pub fn log10_fills(&self) -> f64 {
+    self.log_fills / LN_10
+  }
+
+  /// Roughly how long a depth-first solve would take to find a fill: the
+  /// cost of the whole tree, spread over the fills in it.
+  pub fn eta(&self) -> Duration {
+    let log_letters = self.log_cost - log_add(0.0, self.log_fills);
+    let secs = log_letters.exp() * self.letter_time.as_secs_f64();
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+  }
+}
+
+/// The log of the sum of two numbers given as logs.
+fn log_add(a: f64, b: f64) -> f64 {
+  let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+  if lo == f64::NEG_INFINITY {
+    return hi;
+  }
+  hi + (lo - hi).exp().ln_1p()
+}
+
+impl Solver {
+  /// Dives from the current node to a fill or a dead end, committing a random
+  /// letter that propagates at each level, and takes the dive back. Returns
+  /// Knuth's estimates of the log of the fills and of the letters tried
+  /// below the node, and how many letters the dive tried.
+  fn probe<R: Rng>(&mut self, rng: &mut R) -> (f64, f64, usize) {
+    let mark = self.trail.len();
+    // The log of the number of nodes at the depth of the dive, if every
+    // node were like the ones it passed through.
+    let mut log_width = 0.0;
+    let mut log_cost = f64::NEG_INFINITY;
+    let mut tried = 0;
+    let log_fills = loop {
+      let (ci, set) = match self.pick_cell(VariableOrder::Fewest) {
+        Branch::At(choice) => choice,
+        Branch::Complete => break log_width,
+        Branch::DeadEnd => break f64::NEG_INFINITY,
+      };
+      log_cost = log_add(log_cost, log_width + (set.len() as f64).ln());
+      let mut viable = vec![];
+      for o in set.indices() {
+        let before = self.trail.len();
+        tried += 1;
+        if self.commit_ord(ci, o).is_some() {
+          viable.push(o);
+        }
+        self.undo(before);
+      }
+      let o = match viable.choose(rng) {
+        Some(o) => *o,
+        None => break f64::NEG_INFINITY,
+      };
+      log_width += (viable.len() as f64).ln();
+      self.commit_ord(ci, o);
+    };
+    self.undo(mark);
+    (log_fills, log_cost, tried)
+  }
+
+  /// Averages `probes` probes from the current node.
+  pub(super) fn estimate<R: Rng>(&mut self, probes: usize, rng: &mut R) -> Estimate {
+    let start = Instant::now();
+    let (mut log_fills, mut log_cost, mut tried) = (f64::NEG_INFINITY, f64::NEG_INFINITY, 0);
+    for _ in 0..probes {
+      let (fills, cost, letters) = self.probe(rng);
+      log_fills = log_add(log_fills, fills);
+      log_cost = log_add(log_cost, cost);
+      tried += letters;
+    }
+    let log_probes = (probes.max(1) as f64).ln();
+    Estimate {
+      log_fills: log_fills - log_probes,
+      log_cost: log_cost - log_probes,
+      letter_time: start.elapsed().div_f64(tried.max(1) as f64),
+    }
+  }
+}
+
+impl Grid {
+  /// Estimates how many fills the grid has and how long `solve` would take
+  /// to find one, from `probes` random probes down the search tree. Cheap
+  /// next to a solve, so batch jobs can use it to skip hopeless grids. None
+  /// if the grid can't be filled at all.
+  pub fn estimate<R: Rng>(
+    &self,
+    dictionary: &Dictionary,
+    probes: usize,
+    rng: &mut R,
+  ) -> Option<Estimate> {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let (mut solver, _) = self.prepare(&puzzle, dictionary)?;
+    Some(solver.estimate(probes, rng))
+  }
+}
@@ -0,0 +1,224 @@
+use super::{Grid, Puzzle, Search, SolveOptions, SolveResult, Solver, Square, Strategy};
+use rand::{rngs::StdRng, SeedableRng};
+use std::fmt;
+use std::sync::Arc;
+use words::dictionary::Dictionary;
+
+// Nodes each check may visit while shrinking a failure's core.
+const CORE_NODES: usize = 100000;
+
+/// An entry of the grid, by its first square and direction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+  pub start: (usize, usize),
+  pub direction: u8,
+}
+
+impl fmt::Display for Entry {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let direction = if self.direction == 0 {
+      "across"
+    } else {
+      "down"
+    };
+    write!(f, "{:?} {}", self.start, direction)
+  }
+}
+
+/// Why `Grid::solve` found no fill.
+#[derive(Clone, Debug)]
+pub struct Failure {
+  // Fixed squares whose letters the fixed squares before them ruled out.
+  pub rejected: Vec<(usize, usize)>,
+  // Entries left with no words by the fixed squares, before any search.
+  pub emptied: Vec<Entry>,
+  // Fixed squares which can't all hold together, though removing any one of
+  // them could, and the entries they leave with no words. Both are empty
+  // unless `SolveOptions::explain` asked for them and such a set was found.
+  pub core: Vec<(usize, usize)>,
+  pub core_entries: Vec<Entry>,
+  // Whether every check shrinking the core finished. A check cut short by
+  // its node limit or the solve's options keeps its square, so the core may
+  // hold more than it needs.
+  pub core_minimal: bool,
+  // Whether it took an exhaustive search to find there was no fill.
+  pub exhausted: bool,
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let list = |items: Vec<String>| items.join(", ");
+    let mut parts = vec![];
+    if !self.rejected.is_empty() {
+      let squares = self.rejected.iter().map(|p| format!("{:?}", p)).collect();
+      parts.push(format!("fixed {} conflict", list(squares)));
+    }
+    if !self.emptied.is_empty() {
+      let entries = self.emptied.iter().map(|e| e.to_string()).collect();
+      parts.push(format!("no words for {}", list(entries)));
+    }
+    if !self.core.is_empty() {
+      let squares = self.core.iter().map(|p| format!("{:?}", p)).collect();
+      let entries = self.core_entries.iter().map(|e| e.to_string()).collect();
+      let hedge = if self.core_minimal { "" } else { " (some perhaps not needed)" };
+      parts.push(format!("{}{} can't fill {}", list(squares), hedge, list(entries)));
+    }
+    if parts.is_empty() {
+      parts.push(if self.exhausted {
+        "no fill exists".to_string()
+      } else {
+        "no words fit".to_string()
+      });
+    }
+    write!(f, "{}", parts.join("; "))
+  }
+}
+
+/// What a check of whether some fixed letters can all hold together found.
+enum Check {
+  // They can't, leaving these lines with no words.
+  Refuted(Vec<usize>),
+  Holds,
+  // The search reached a limit before it could tell.
+  CutShort,
+}
+
+impl Solver {
+  /// Whether the cell letters in `fixed` can't all hold together. If
+  /// propagation shows it, the lines left with no words are the ones
+  /// blamed, or every line through the cells if it takes a search within
+  /// `CORE_NODES` and the deadline and cancel token of `options`. The
+  /// solver is back at the root afterwards.
+  fn refutes(&mut self, fixed: &[(usize, char)], options: &SolveOptions) -> Check {
+    let refuted = self.refutes_from_root(fixed, options);
+    self.restart();
+    // Nogoods only hold under the fixed letters they were learned with.
+    self.nogoods.clear();
+    self.nogood_index.clear();
+    refuted
+  }
+
+  fn refutes_from_root(&mut self, fixed: &[(usize, char)], options: &SolveOptions) -> Check {
+    for &(ci, ch) in fixed {
+      if !self.commit_char(ci, ch) {
+        return Check::Refuted(std::mem::take(&mut self.dead));
+      }
+    }
+    if self.prefilter().is_none() {
+      return Check::Refuted(std::mem::take(&mut self.dead));
+    }
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut observer = ();
+    let options = SolveOptions {
+      deadline: options.deadline,
+      max_nodes: Some(CORE_NODES),
+      cancel: options.cancel.clone(),
+      ..SolveOptions::default()
+    };
+    let mut search = Search::new(Strategy::default(), &options, &mut rng, &mut observer);
+    match self.run(&mut search, &mut vec![]) {
+      SolveResult::None => Check::Refuted(
+        fixed
+          .iter()
+          .flat_map(|&(ci, _)| self.puzzle.cells[ci].lines.iter())
+          .map(|&(li, _)| li as usize)
+          .collect(),
+      ),
+      SolveResult::Solution(_) => Check::Holds,
+      SolveResult::Incomplete(_) => Check::CutShort,
+    }
+  }
+}
+
+pub(super) fn entries(puzzle: &Puzzle, mut lis: Vec<usize>) -> Vec<Entry> {
+  lis.sort_unstable();
+  lis.dedup();
+  lis
+    .into_iter()
+    .map(|li| {
+      let line = &puzzle.lines[li];
+      Entry {
+        start: puzzle.cell_positions[line.cell_indices[0] as usize],
+        direction: line.direction,
+      }
+    })
+    .collect()
+}
+
+impl Grid {
+  /// Works out why the grid has no fill, once a solve has failed. If
+  /// `options` asks for a core, it's shrunk from every fixed square by
+  /// dropping those it holds without.
+  pub(super) fn explain(
+    &self,
+    puzzle: &Arc<Puzzle>,
+    dictionary: &Dictionary,
+    exhausted: bool,
+    options: &SolveOptions,
+  ) -> Failure {
+    let fixed: Vec<(usize, char)> = puzzle
+      .cell_positions
+      .iter()
+      .enumerate()
+      .filter_map(|(ci, position)| match self.squares[position] {
+        Square::Fixed(ch) => Some((ci, ch)),
+        _ => None,
+      })
+      .collect();
+    let mut solver = Solver::new(puzzle.clone(), dictionary);
+    let mut rejected = vec![];
+    let mut emptied = vec![];
+    for &(ci, ch) in &fixed {
+      let mark = solver.trail.len();
+      if !solver.commit_char(ci, ch) {
+        rejected.push(puzzle.cell_positions[ci]);
+        emptied.append(&mut solver.dead);
+        solver.undo(mark);
+      }
+    }
+    if solver.prefilter().is_none() {
+      emptied.append(&mut solver.dead);
+    }
+    solver.undo(0);
+    let mut core = fixed;
+    let mut dead = None;
+    let mut minimal = false;
+    if options.explain {
+      if let Check::Refuted(lines) = solver.refutes(&core, options) {
+        dead = Some(lines);
+        minimal = true;
+      }
+    }
+    if dead.is_some() {
+      let mut i = 0;
+      while i < core.len() {
+        let mut without = core.clone();
+        without.remove(i);
+        match solver.refutes(&without, options) {
+          Check::Refuted(lines) => {
+            core = without;
+            dead = Some(lines);
+          }
+          Check::Holds => i += 1,
+          Check::CutShort => {
+            minimal = false;
+            i += 1;
+          }
+        }
+      }
+    } else {
+      core.clear();
+    }
+    Failure {
+      rejected,
+      emptied: entries(puzzle, emptied),
+      core: core
+        .iter()
+        .map(|&(ci, _)| puzzle.cell_positions[ci])
+        .collect(),
+      core_entries: entries(puzzle, dead.unwrap_or_default()),
+      core_minimal: minimal,
+      exhausted,
+    }
+  }
+}
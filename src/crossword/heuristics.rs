@@ -0,0 +1,332 @@
+use super::{
+  Branch, Branching, CancelToken, Choice, Grid, Levels, Observer, Outcome, SolveOptions, Solver,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::cmp::{Ordering, Reverse};
+use std::time::Duration;
+use words::dictionary::Dictionary;
+use words::LetterSet;
+
+/// Which cell or line a depth-first solve branches on next. Cells and lines
+/// which are already down to one letter or word are never picked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VariableOrder {
+  // The fewest letters or words.
+  Fewest,
+  // The fewest letters or words, breaking ties by the most unsettled cells
+  // or lines crossing it.
+  FewestThenDegree,
+  // The fewest letters or words per failure seen in its lines so far.
+  FailureWeighted,
+  // In the longest unsettled line, breaking ties by the fewest letters or
+  // words.
+  LongestFirst,
+}
+
+/// The order a depth-first solve tries the letters or words of a branch in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueOrder {
+  // Random, weighting letters by how many words support them in the
+  // crossing lines.
+  Sampled,
+  // Whatever leaves the most words in the crossing lines first.
+  LeastConstraining,
+}
+
+/// Everything that decides how a depth-first solve branches.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Strategy {
+  pub branching: Branching,
+  pub variables: VariableOrder,
+  pub values: ValueOrder,
+}
+
+impl Default for Strategy {
+  fn default() -> Strategy {
+    Strategy {
+      branching: Branching::Letter,
+      variables: VariableOrder::Fewest,
+      values: ValueOrder::Sampled,
+    }
+  }
+}
+
+impl Strategy {
+  /// Every combination of branching and orderings.
+  pub fn all() -> Vec<Strategy> {
+    let mut strategies = vec![];
+    for &branching in &[Branching::Letter, Branching::Word] {
+      for &variables in &[
+        VariableOrder::Fewest,
+        VariableOrder::FewestThenDegree,
+        VariableOrder::FailureWeighted,
+        VariableOrder::LongestFirst,
+      ] {
+        for &values in &[ValueOrder::Sampled, ValueOrder::LeastConstraining] {
+          strategies.push(Strategy {
+            branching,
+            variables,
+            values,
+          });
+        }
+      }
+    }
+    strategies
+  }
+}
+
+/// How long solves took under one strategy, from `Grid::benchmark`.
+pub struct Timing {
+  pub strategy: Strategy,
+  // The median of the solves' search times.
+  pub elapsed: Duration,
+  // How many of the solves found a fill.
+  pub solved: usize,
+}
+
+/// Picks the item with the smallest key, the first one on ties.
+fn smallest<T, I: Iterator<Item = (T, (f64, f64))>>(items: I) -> Option<T> {
+  let mut best: Option<(T, (f64, f64))> = None;
+  for (item, key) in items {
+    let better = match &best {
+      Some((_, best_key)) => key.partial_cmp(best_key) == Some(Ordering::Less),
+      None => true,
+    };
+    if better {
+      best = Some((item, key));
+    }
+  }
+  best.map(|(item, _)| item)
+}
+
+impl Solver {
+  /// How many cells of each line still have more than one letter.
+  fn unsettled_cells(&self) -> Vec<usize> {
+    self
+      .puzzle
+      .lines
+      .iter()
+      .map(|line| {
+        let cells = line.cell_indices.iter();
+        cells
+          .filter(|ci| self.cell_set(**ci as usize).len() > 1)
+          .count()
+      })
+      .collect()
+  }
+
+  /// The lines crossing line `li`.
+  fn crossings(&self, li: usize) -> impl Iterator<Item = usize> + '_ {
+    let line = &self.puzzle.lines[li];
+    let across = 1 - line.direction as usize;
+    line
+      .cell_indices
+      .iter()
+      .map(move |ci| self.puzzle.cells[*ci as usize].lines[across].0 as usize)
+  }
+
+  /// Counts a failure against each line which ran out of words at the most
+  /// recent dead end, for `VariableOrder::FailureWeighted`.
+  pub(super) fn weigh_failure(&mut self) {
+    for &li in &self.dead {
+      self.weights[li] += 1;
+    }
+  }
+
+  /// The cell to branch on next under `order`, with its letters.
+  pub(super) fn pick_cell(&self, order: VariableOrder) -> Branch<(usize, LetterSet)> {
+    let mut sets = Vec::with_capacity(self.puzzle.cells.len());
+    for ci in 0..self.puzzle.cells.len() {
+      let set = self.cell_set(ci);
+      match set.len() {
+        0 => return Branch::DeadEnd,
+        1 => {}
+        _ => sets.push((ci, set)),
+      }
+    }
+    if sets.is_empty() {
+      return Branch::Complete;
+    }
+    let unsettled = match order {
+      VariableOrder::FewestThenDegree | VariableOrder::LongestFirst => self.unsettled_cells(),
+      _ => vec![],
+    };
+    let keys = sets.into_iter().map(|(ci, set)| {
+      let n = set.len() as f64;
+      let lis = self.puzzle.cells[ci].lines;
+      let (l0, l1) = (lis[0].0 as usize, lis[1].0 as usize);
+      let key = match order {
+        VariableOrder::Fewest => (n, 0.0),
+        VariableOrder::FewestThenDegree => (n, -((unsettled[l0] + unsettled[l1]) as f64)),
+        VariableOrder::FailureWeighted => (n / (self.weights[l0] + self.weights[l1]) as f64, 0.0),
+        VariableOrder::LongestFirst => {
+          let length = |li: usize| match unsettled[li] {
+            0 => 0,
+            _ => self.line_states[li].length(),
+          };
+          (-(length(l0).max(length(l1)) as f64), n)
+        }
+      };
+      ((ci, set), key)
+    });
+    Branch::At(smallest(keys).unwrap())
+  }
+
+  fn pick_line(&self, order: VariableOrder) -> Branch<usize> {
+    let mut open = vec![];
+    for (li, line) in self.line_states.iter().enumerate() {
+      match line.word_count() {
+        0 => return Branch::DeadEnd,
+        1 => {}
+        _ => open.push(li),
+      }
+    }
+    if open.is_empty() {
+      return Branch::Complete;
+    }
+    let unsettled = |li: usize| self.line_states[li].word_count() > 1;
+    let keys = open.into_iter().map(|li| {
+      let line = &self.line_states[li];
+      let n = line.word_count() as f64;
+      let key = match order {
+        VariableOrder::Fewest => (n, 0.0),
+        VariableOrder::FewestThenDegree => {
+          let degree = self.crossings(li).filter(|l| unsettled(*l)).count();
+          (n, -(degree as f64))
+        }
+        VariableOrder::FailureWeighted => {
+          let crossing: u32 = self
+            .crossings(li)
+            .filter(|l| unsettled(*l))
+            .map(|l| self.weights[l])
+            .sum();
+          (n / (self.weights[li] + crossing) as f64, 0.0)
+        }
+        VariableOrder::LongestFirst => (-(line.length() as f64), n),
+      };
+      (li, key)
+    });
+    Branch::At(smallest(keys).unwrap())
+  }
+
+  /// The letters of `set` for cell `ci`, most supported by its two lines
+  /// first.
+  fn least_constraining_letters(&self, ci: usize, set: &LetterSet) -> Vec<u8> {
+    let lis = &self.puzzle.cells[ci].lines;
+    let support = |o: u8| {
+      let count = |(li, pos): (u32, u8)| self.line_states[li as usize].letter_count(pos, o);
+      count(lis[0]) as u64 * count(lis[1]) as u64
+    };
+    let mut letters: Vec<u8> = set.indices().collect();
+    letters.sort_by_key(|o| std::cmp::Reverse(support(*o)));
+    letters
+  }
+
+  /// The words left in line `li`, those leaving the most words in the
+  /// crossing lines first.
+  fn least_constraining_words(&self, li: usize) -> Vec<u32> {
+    let line = &self.line_states[li];
+    let cells = &self.puzzle.lines[li].cell_indices;
+    let across = 1 - self.puzzle.lines[li].direction as usize;
+    let mut scored: Vec<(u32, f64)> = line
+      .ids()
+      .map(|id| {
+        let word = line.words.word(id);
+        let score = cells.iter().zip(word).map(|(ci, o)| {
+          let (other, pos) = self.puzzle.cells[*ci as usize].lines[across];
+          (self.line_states[other as usize].letter_count(pos, *o) as f64).ln()
+        });
+        (id, score.sum())
+      })
+      .collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    scored.into_iter().map(|(id, _)| id).collect()
+  }
+
+  /// The choices to try at the next branch, in the order to try them, and
+  /// the levels which ruled out any others. An error once the search has
+  /// reached a dead end or a complete fill.
+  pub(super) fn branch<R: Rng>(
+    &self,
+    strategy: Strategy,
+    rng: &mut R,
+  ) -> Result<(Vec<Choice>, Levels), Branch<()>> {
+    match strategy.branching {
+      Branching::Letter => match self.pick_cell(strategy.variables) {
+        Branch::At((ci, set)) => {
+          let letters = match strategy.values {
+            ValueOrder::Sampled => self.sample_letters(ci, &set, rng),
+            ValueOrder::LeastConstraining => self.least_constraining_letters(ci, &set),
+          };
+          let choices = letters.into_iter().map(|o| Choice::Letter(ci, o));
+          Ok((choices.collect(), self.cell_reasons(ci)))
+        }
+        Branch::DeadEnd => Err(Branch::DeadEnd),
+        Branch::Complete => Err(Branch::Complete),
+      },
+      Branching::Word => match self.pick_line(strategy.variables) {
+        Branch::At(li) => {
+          let words = match strategy.values {
+            ValueOrder::Sampled => {
+              let mut ids: Vec<_> = self.line_states[li].ids().collect();
+              ids.shuffle(rng);
+              ids
+            }
+            ValueOrder::LeastConstraining => self.least_constraining_words(li),
+          };
+          let choices = words.into_iter().map(|id| Choice::Word(li, id));
+          Ok((choices.collect(), self.line_states[li].reasons.clone()))
+        }
+        Branch::DeadEnd => Err(Branch::DeadEnd),
+        Branch::Complete => Err(Branch::Complete),
+      },
+    }
+  }
+}
+
+impl Grid {
+  /// Solves copies of the grid under every strategy once from each of
+  /// `seeds`, for at most `limit` a solve, and returns the median time each
+  /// strategy took, most fills then fastest first. Only the search is timed,
+  /// not working out why a solve failed. Each solve reports to `observer`.
+  /// Once `cancel` is cancelled, the strategy being timed is dropped and only
+  /// those timed by then are returned.
+  pub fn benchmark(
+    &self,
+    dictionary: &Dictionary,
+    limit: Duration,
+    seeds: &[u64],
+    cancel: &CancelToken,
+    observer: &mut dyn Observer,
+  ) -> Vec<Timing> {
+    let mut timings = vec![];
+    for strategy in Strategy::all() {
+      let mut times = vec![];
+      let mut solved = 0;
+      for &seed in seeds {
+        let mut grid = self.clone();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let options = SolveOptions {
+          cancel: Some(cancel.clone()),
+          ..SolveOptions::within(limit)
+        };
+        let (outcome, stats) = grid.solve(dictionary, strategy, &options, &mut rng, observer);
+        times.push(stats.elapsed);
+        if let Outcome::Solved = outcome {
+          solved += 1;
+        }
+      }
+      if cancel.is_cancelled() {
+        break;
+      }
+      times.sort();
+      timings.push(Timing {
+        strategy,
+        elapsed: times.get(times.len() / 2).copied().unwrap_or_default(),
+        solved,
+      });
+    }
+    timings.sort_by_key(|timing| (Reverse(timing.solved), timing.elapsed));
+    timings
+  }
+}
@@ -0,0 +1,93 @@
+use super::{Closeness, Search, Trace};
+use rand::Rng;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Lets another thread stop a solve. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+  pub fn new() -> CancelToken {
+    CancelToken::default()
+  }
+
+  /// Stops every solve holding a clone of the token at its next node.
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::Relaxed);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::Relaxed)
+  }
+}
+
+/// Limits on a depth-first solve. With none set, it runs until it finds a
+/// fill or shows there is none.
+#[derive(Clone, Debug, Default)]
+pub struct SolveOptions {
+  pub deadline: Option<Instant>,
+  // Nodes the solve may visit, counting from where it starts or resumes.
+  pub max_nodes: Option<usize>,
+  pub cancel: Option<CancelToken>,
+  // How to pick the partial fill to leave in the grid if the solve doesn't
+  // finish a fill. Without it, the grid is left as the search stopped.
+  pub keep_closest: Option<Closeness>,
+  // Where to write the solve's commit and backtrack events.
+  pub trace: Option<Trace>,
+  // Whether a failed solve should find a core of fixed squares which can't
+  // all hold together. It can take many more searches than the solve did,
+  // each within the deadline and cancel token above.
+  pub explain: bool,
+}
+
+impl SolveOptions {
+  /// Options with just a deadline, `time` from now.
+  pub fn within(time: Duration) -> SolveOptions {
+    SolveOptions {
+      deadline: Some(Instant::now() + time),
+      ..SolveOptions::default()
+    }
+  }
+}
+
+/// Which limit of a `SolveOptions` stopped a solve.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+  Deadline,
+  Nodes,
+  Cancelled,
+}
+
+impl fmt::Display for Limit {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let reason = match self {
+      Limit::Deadline => "at the deadline",
+      Limit::Nodes => "at the node limit",
+      Limit::Cancelled => "on cancellation",
+    };
+    write!(f, "{}", reason)
+  }
+}
+
+impl<R: Rng> Search<'_, R> {
+  /// The limit the search has reached, if any.
+  pub(super) fn limit_reached(&self) -> Option<Limit> {
+    if let Some(token) = &self.options.cancel {
+      if token.is_cancelled() {
+        return Some(Limit::Cancelled);
+      }
+    }
+    if let Some(max_nodes) = self.options.max_nodes {
+      if self.nodes - self.first_node >= max_nodes {
+        return Some(Limit::Nodes);
+      }
+    }
+    match self.options.deadline {
+      Some(deadline) if Instant::now() >= deadline => Some(Limit::Deadline),
+      _ => None,
+    }
+  }
+}
@@ -0,0 +1,325 @@
+use super::{ord_char, Grid, Objective, Puzzle, Solver, Square};
+use rand::{seq::SliceRandom, Rng};
+use std::collections::HashSet;
+use std::sync::Arc;
+use words::dictionary::Dictionary;
+use words::LetterSet;
+
+// Chance that a step picks a random conflicting line or word instead of the
+// worst line or best word.
+const NOISE: f64 = 0.1;
+
+// Swaps allowed for repairing the fill after each change `Grid::improve`
+// tries, per line of the puzzle.
+const REPAIR_STEPS: usize = 20;
+
+/// A word for every line of a puzzle, where crossing lines may disagree on
+/// the letter of the cell they share. Each step swaps a word of the line with
+/// the most disagreements for the one which disagrees least.
+struct Walk<'a> {
+  solver: &'a Solver,
+  // The words each line can take after the fixed squares and prefilter.
+  candidates: Vec<Vec<u32>>,
+  words: Vec<u32>,
+  // Lengths and ids of the words in use, which no other line may take.
+  used: HashSet<(usize, u32)>,
+  // How much a disagreement at each cell counts. Cells whose disagreement a
+  // swap couldn't remove count for more, to steer the walk out of minima.
+  weights: Vec<u32>,
+  // A line the walk mustn't swap.
+  frozen: Option<usize>,
+}
+
+impl<'a> Walk<'a> {
+  fn new(solver: &'a Solver) -> Walk<'a> {
+    Walk {
+      solver,
+      candidates: solver
+        .line_states
+        .iter()
+        .map(|line| line.ids().collect())
+        .collect(),
+      words: vec![],
+      used: HashSet::new(),
+      weights: vec![1; solver.puzzle.cells.len()],
+      frozen: None,
+    }
+  }
+
+  /// Starts from a distinct random word for every line. None if some line
+  /// runs out of words.
+  fn randomize<R: Rng>(&mut self, rng: &mut R) -> Option<()> {
+    let mut words = vec![];
+    self.used.clear();
+    for (li, candidates) in self.candidates.iter().enumerate() {
+      let length = self.solver.puzzle.lines[li].length();
+      let unused: Vec<_> = candidates
+        .iter()
+        .filter(|id| !self.used.contains(&(length, **id)))
+        .collect();
+      let id = **unused.choose(rng)?;
+      self.used.insert((length, id));
+      words.push(id);
+    }
+    self.words = words;
+    Some(())
+  }
+
+  fn start_from(&mut self, words: Vec<u32>) {
+    let lines = &self.solver.puzzle.lines;
+    self.used = lines
+      .iter()
+      .zip(&words)
+      .map(|(line, id)| (line.length(), *id))
+      .collect();
+    self.words = words;
+  }
+
+  fn letter(&self, li: usize, pos: usize) -> u8 {
+    self.solver.line_states[li].words.word(self.words[li])[pos]
+  }
+
+  fn disagrees(&self, ci: usize) -> bool {
+    let [(l0, p0), (l1, p1)] = self.solver.puzzle.cells[ci].lines;
+    self.letter(l0 as usize, p0 as usize) != self.letter(l1 as usize, p1 as usize)
+  }
+
+  /// The weighted disagreements between line `li` and its crossing lines.
+  fn line_cost(&self, li: usize) -> u32 {
+    let cells = self.solver.puzzle.lines[li].cell_indices.iter();
+    cells
+      .map(|ci| *ci as usize)
+      .filter(|ci| self.disagrees(*ci))
+      .map(|ci| self.weights[ci])
+      .sum()
+  }
+
+  fn score(&self, li: usize, id: u32) -> u32 {
+    let length = self.solver.puzzle.lines[li].length();
+    match self.solver.scores.get(&length) {
+      Some(scores) => scores[id as usize],
+      None => 0,
+    }
+  }
+
+  fn swap(&mut self, li: usize, id: u32) {
+    let length = self.solver.puzzle.lines[li].length();
+    self.used.remove(&(length, self.words[li]));
+    self.used.insert((length, id));
+    self.words[li] = id;
+  }
+
+  /// Swaps the word of one conflicting line. False if every crossing already
+  /// agrees.
+  fn step<R: Rng>(&mut self, rng: &mut R) -> bool {
+    let costs: Vec<(usize, u32)> = (0..self.words.len())
+      .filter(|li| Some(*li) != self.frozen)
+      .map(|li| (li, self.line_cost(li)))
+      .filter(|(_, cost)| *cost > 0)
+      .collect();
+    let worst = costs.iter().map(|(_, cost)| *cost).max().unwrap_or(0);
+    let li = if rng.gen_bool(NOISE) {
+      costs.choose(rng).map(|(li, _)| *li)
+    } else {
+      let worst: Vec<_> = costs.iter().filter(|(_, cost)| *cost == worst).collect();
+      worst.choose(rng).map(|(li, _)| *li)
+    };
+    let li = match li {
+      Some(li) => li,
+      None => return false,
+    };
+    let solver = self.solver;
+    let line = &solver.puzzle.lines[li];
+    let across = 1 - line.direction as usize;
+    // The letter each crossing line wants in each cell, and its weight.
+    let wanted: Vec<(u8, u32)> = line
+      .cell_indices
+      .iter()
+      .map(|ci| {
+        let (other, pos) = solver.puzzle.cells[*ci as usize].lines[across];
+        (
+          self.letter(other as usize, pos as usize),
+          self.weights[*ci as usize],
+        )
+      })
+      .collect();
+    let words = &solver.line_states[li].words;
+    let options = self.candidates[li]
+      .iter()
+      .filter(|id| **id != self.words[li] && !self.used.contains(&(line.length(), **id)));
+    let id = if rng.gen_bool(NOISE) {
+      let options: Vec<_> = options.collect();
+      options.choose(rng).map(|id| **id)
+    } else {
+      let keyed = options.map(|id| {
+        let word = words.word(*id);
+        let cost: u32 = word
+          .iter()
+          .zip(&wanted)
+          .filter(|(o, (want, _))| *o != want)
+          .map(|(_, (_, weight))| weight)
+          .sum();
+        (*id, (cost, std::cmp::Reverse(self.score(li, *id))))
+      });
+      let keyed: Vec<_> = keyed.collect();
+      let best = keyed.iter().map(|(_, key)| *key).min();
+      let best: Vec<_> = keyed.iter().filter(|(_, key)| Some(*key) == best).collect();
+      best.choose(rng).map(|(id, _)| *id)
+    };
+    if let Some(id) = id {
+      let cost = self.line_cost(li);
+      self.swap(li, id);
+      if self.line_cost(li) >= cost {
+        for &ci in &line.cell_indices {
+          if self.disagrees(ci as usize) {
+            self.weights[ci as usize] += 1;
+          }
+        }
+      }
+    }
+    true
+  }
+
+  /// Steps until every crossing agrees, or `steps` runs out. Returns whether
+  /// they all agree.
+  fn run<R: Rng>(&mut self, steps: &mut usize, rng: &mut R) -> bool {
+    loop {
+      if *steps == 0 {
+        return (0..self.weights.len()).all(|ci| !self.disagrees(ci));
+      }
+      *steps -= 1;
+      if !self.step(rng) {
+        return true;
+      }
+    }
+  }
+
+  /// The fill's rating by `objective`.
+  fn rate(&self, objective: Objective) -> u64 {
+    let scores = (0..self.words.len()).map(|li| self.score(li, self.words[li]) as u64);
+    match objective {
+      Objective::Total => scores.sum(),
+      Objective::Minimum => scores.min().unwrap_or(0),
+    }
+  }
+
+  fn chars(&self) -> Vec<(usize, char)> {
+    (0..self.solver.puzzle.lines.len())
+      .filter(|li| self.solver.puzzle.lines[*li].direction == 0)
+      .flat_map(|li| {
+        let cells = self.solver.puzzle.lines[li].cell_indices.iter();
+        cells
+          .enumerate()
+          .map(move |(pos, ci)| (*ci as usize, ord_char(self.letter(li, pos))))
+      })
+      .collect()
+  }
+}
+
+impl Grid {
+  /// The id of the word each line of the puzzle spells in the grid, if every
+  /// square has a letter and every line is one of its candidates.
+  fn current_words(&self, puzzle: &Puzzle, solver: &Solver) -> Option<Vec<u32>> {
+    let letter = |ci: &u32| match self.squares[&puzzle.cell_positions[*ci as usize]] {
+      Square::Fixed(ch) | Square::Solved(ch) => LetterSet::index(ch),
+      Square::Empty => None,
+    };
+    let lines = puzzle.lines.iter().zip(&solver.line_states);
+    lines
+      .map(|(line, state)| {
+        let word: Option<Vec<u8>> = line.cell_indices.iter().map(letter).collect();
+        let word = word?;
+        state
+          .ids()
+          .find(|id| state.words.word(*id) == word.as_slice())
+      })
+      .collect()
+  }
+
+  /// Fills the grid by local search rather than depth-first: from a random
+  /// word in every entry, keeps swapping the words of the entries whose
+  /// crossings disagree most, for at most `steps` swaps. Slower than `solve`
+  /// on small grids, but it doesn't bog down on big ones. Returns whether it
+  /// found a fill, leaving the grid as it was if not.
+  pub fn solve_local<R: Rng>(
+    &mut self,
+    dictionary: &Dictionary,
+    mut steps: usize,
+    rng: &mut R,
+  ) -> bool {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let (solver, _) = match self.prepare(&puzzle, dictionary) {
+      Some(prepared) => prepared,
+      None => return false,
+    };
+    let mut walk = Walk::new(&solver);
+    if walk.randomize(rng).is_none() || !walk.run(&mut steps, rng) {
+      return false;
+    }
+    self.clear_solved();
+    self.write_solved(&puzzle, &walk.chars());
+    true
+  }
+
+  /// Improves the grid's current fill by local search, rating each entry
+  /// with `score` and the fill by `objective`. Each round swaps a weak
+  /// entry for a better scoring word and repairs the crossings around it,
+  /// keeping the result if it's valid and rates higher. Stops once `steps`
+  /// swaps have been made. Returns the score of the fill left in the grid,
+  /// or None if the grid isn't completely and validly filled.
+  pub fn improve<S: Fn(&str) -> u32, R: Rng>(
+    &mut self,
+    dictionary: &Dictionary,
+    score: S,
+    objective: Objective,
+    mut steps: usize,
+    rng: &mut R,
+  ) -> Option<u64> {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let (mut solver, _) = self.prepare(&puzzle, dictionary)?;
+    solver.score_words(score);
+    let mut best = self.current_words(&puzzle, &solver)?;
+    let mut walk = Walk::new(&solver);
+    walk.start_from(best.clone());
+    let mut rating = walk.rate(objective);
+    while steps > 0 {
+      steps -= 1;
+      walk.start_from(best.clone());
+      // The weakest entry, or a random one if the objective isn't held back
+      // by it.
+      let li = match objective {
+        Objective::Minimum if !rng.gen_bool(NOISE) => {
+          let lis = 0..best.len();
+          lis.min_by_key(|li| walk.score(*li, best[*li])).unwrap()
+        }
+        _ => rng.gen_range(0, best.len()),
+      };
+      let length = puzzle.lines[li].length();
+      let floor = walk.score(li, best[li]);
+      let better: Vec<_> = walk.candidates[li]
+        .iter()
+        .filter(|id| walk.score(li, **id) > floor && !walk.used.contains(&(length, **id)))
+        .cloned()
+        .collect();
+      let id = match better.choose(rng) {
+        Some(id) => *id,
+        None => continue,
+      };
+      walk.swap(li, id);
+      walk.frozen = Some(li);
+      let mut repair = steps.min(REPAIR_STEPS * best.len());
+      steps -= repair;
+      let repaired = walk.run(&mut repair, rng);
+      steps += repair;
+      walk.frozen = None;
+      if repaired && walk.rate(objective) > rating {
+        rating = walk.rate(objective);
+        best = walk.words.clone();
+      }
+    }
+    walk.start_from(best);
+    self.clear_solved();
+    self.write_solved(&puzzle, &walk.chars());
+    Some(rating)
+  }
+}
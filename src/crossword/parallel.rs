@@ -0,0 +1,294 @@
+use super::{
+  CancelToken, Choice, Frame, Grid, Limit, Outcome, Puzzle, Search, SolveOptions, SolveResult,
+  SolveStats, Solver, Strategy,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+use words::dictionary::Dictionary;
+
+/// What the workers of a parallel solve share: subtrees waiting for a
+/// worker, each as the path of choices from the root down to it, and the
+/// first fill found.
+struct Pool {
+  queue: Mutex<Vec<Vec<Choice>>>,
+  ready: Condvar,
+  workers: usize,
+  // How many workers are waiting for a subtree, and how many subtrees are
+  // queued, kept outside the lock so busy workers can check them cheaply.
+  waiting: AtomicUsize,
+  queued: AtomicUsize,
+  // Cancelled once the solve is over, which every worker checks at each
+  // node.
+  stop: CancelToken,
+  solution: Mutex<Option<Vec<(usize, char)>>>,
+  // The limit of the solve's options which stopped it, if one did.
+  limit: Mutex<Option<Limit>>,
+  // Every worker's stats, added up as they finish.
+  stats: Mutex<SolveStats>,
+}
+
+impl Pool {
+  fn new(workers: usize, tasks: Vec<Vec<Choice>>) -> Pool {
+    Pool {
+      queued: AtomicUsize::new(tasks.len()),
+      queue: Mutex::new(tasks),
+      ready: Condvar::new(),
+      workers,
+      waiting: AtomicUsize::new(0),
+      stop: CancelToken::new(),
+      solution: Mutex::new(None),
+      limit: Mutex::new(None),
+      stats: Mutex::new(SolveStats::default()),
+    }
+  }
+
+  /// Whether a worker is waiting with nothing queued for it.
+  fn hungry(&self) -> bool {
+    self.waiting.load(Ordering::Relaxed) > self.queued.load(Ordering::Relaxed)
+  }
+
+  fn give(&self, path: Vec<Choice>) {
+    let mut queue = self.queue.lock().unwrap();
+    queue.push(path);
+    self.queued.store(queue.len(), Ordering::Relaxed);
+    self.ready.notify_one();
+  }
+
+  /// Waits for a subtree to search. None once the solve is over, either
+  /// because a fill was found or because every worker ran out of subtrees.
+  fn take(&self) -> Option<Vec<Choice>> {
+    let mut queue = self.queue.lock().unwrap();
+    loop {
+      if self.stop.is_cancelled() {
+        return None;
+      }
+      if let Some(path) = queue.pop() {
+        self.queued.store(queue.len(), Ordering::Relaxed);
+        return Some(path);
+      }
+      if self.waiting.fetch_add(1, Ordering::Relaxed) + 1 == self.workers {
+        self.stop.cancel();
+        self.ready.notify_all();
+        return None;
+      }
+      queue = self.ready.wait(queue).unwrap();
+      self.waiting.fetch_sub(1, Ordering::Relaxed);
+    }
+  }
+
+  /// Ends the solve, with the fill found if there is one. Only the first
+  /// fill is kept.
+  fn finish(&self, chars: Option<Vec<(usize, char)>>) {
+    let _queue = self.queue.lock().unwrap();
+    let mut solution = self.solution.lock().unwrap();
+    if solution.is_none() {
+      *solution = chars;
+    }
+    self.stop.cancel();
+    self.ready.notify_all();
+  }
+
+  /// Ends the solve because a worker reached `limit`, unless it's already
+  /// over.
+  fn halt(&self, limit: Limit) {
+    let _queue = self.queue.lock().unwrap();
+    if self.stop.is_cancelled() {
+      return;
+    }
+    self.limit.lock().unwrap().get_or_insert(limit);
+    self.stop.cancel();
+    self.ready.notify_all();
+  }
+
+  /// How a worker's search ended, once it's over.
+  fn end(&self, result: SolveResult, stopped: Option<Limit>) {
+    match (result, stopped) {
+      (SolveResult::Solution(chars), _) => self.finish(Some(chars)),
+      (SolveResult::None, _) => self.finish(None),
+      (SolveResult::Incomplete(_), Some(limit)) => self.halt(limit),
+      // Another worker ended the solve, or the subtree was handed on.
+      (SolveResult::Incomplete(_), None) => {}
+    }
+  }
+}
+
+/// The options each worker of a parallel solve searches under: the limits
+/// of `options`, applying to each worker on its own, and its trace.
+fn worker_options(options: &SolveOptions) -> SolveOptions {
+  SolveOptions {
+    keep_closest: None,
+    explain: false,
+    ..options.clone()
+  }
+}
+
+/// A worker's part in a parallel solve: the pool it hands subtrees to, and
+/// the choices it committed before its search began.
+pub(super) struct Split<'a> {
+  pool: &'a Pool,
+  root: &'a [Choice],
+}
+
+impl Solver {
+  /// Hands the subtree of the shallowest untried choice to a waiting worker,
+  /// if there is one.
+  pub(super) fn offer(&self, split: &Split, stack: &mut [Frame]) {
+    if !split.pool.hungry() {
+      return;
+    }
+    let i = match stack.iter().position(|frame| !frame.untried.is_empty()) {
+      Some(i) => i,
+      None => return,
+    };
+    // The first untried choice is the one which would be tried last.
+    let choice = stack[i].untried.remove(0);
+    // Another worker covers that choice now, so if the rest fail, they can
+    // only be blamed on every choice above them.
+    for level in 1..=i {
+      stack[i].conflicts.insert(level);
+    }
+    let mut path = split.root.to_vec();
+    path.extend(stack[..i].iter().map(|frame| frame.chosen.unwrap().0));
+    path.push(choice);
+    split.pool.give(path);
+  }
+
+  /// Searches subtrees from the pool until the solve is over.
+  fn work(mut self, pool: &Pool, strategy: Strategy, options: &SolveOptions, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut observer = ();
+    // Carried from subtree to subtree, so `options.max_nodes` limits the
+    // worker rather than each subtree.
+    let mut nodes = 0;
+    let mut stats = SolveStats::default();
+    while let Some(path) = pool.take() {
+      self.restart();
+      // Nogoods learned below another subtree's path needn't hold here.
+      self.nogoods.clear();
+      self.nogood_index.clear();
+      if path.iter().any(|choice| self.commit(*choice).is_none()) {
+        continue;
+      }
+      let mut search = Search::new(strategy, options, &mut rng, &mut observer);
+      search.split = Some(Split { pool, root: &path });
+      search.stop = Some(pool.stop.clone());
+      search.nodes = nodes;
+      let result = self.run(&mut search, &mut vec![]);
+      nodes = search.nodes;
+      stats.add(&search.stats);
+      match result {
+        // The subtree failed, but others may still be searched.
+        SolveResult::None => {}
+        result => pool.end(result, search.stopped),
+      }
+    }
+    pool.stats.lock().unwrap().add(&stats);
+  }
+}
+
+impl Grid {
+  /// Solves the grid depth-first on `threads` threads, branching as
+  /// `strategy` says. One thread starts from the root, and whenever another
+  /// is idle, a busy one hands it the subtree of its shallowest untried
+  /// choice. All of them stop as soon as any finds a fill, or any reaches a
+  /// limit of `options`; `max_nodes` limits each thread on its own. The
+  /// stats add up every thread's.
+  pub fn solve_parallel<R: Rng>(
+    &mut self,
+    dictionary: &Dictionary,
+    strategy: Strategy,
+    threads: usize,
+    options: &SolveOptions,
+    rng: &mut R,
+  ) -> (Outcome, SolveStats) {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let solver = match self.prepare(&puzzle, dictionary) {
+      Some((solver, _)) => solver,
+      None => {
+        let failure = self.explain(&puzzle, dictionary, false, options);
+        return (Outcome::Failed(failure), SolveStats::default());
+      }
+    };
+    let threads = threads.max(1);
+    let pool = Pool::new(threads, vec![vec![]]);
+    let seeds: Vec<u64> = (0..threads).map(|_| rng.gen()).collect();
+    let worker = worker_options(options);
+    let start = Instant::now();
+    thread::scope(|scope| {
+      for seed in seeds {
+        let solver = solver.clone();
+        let (pool, worker) = (&pool, &worker);
+        scope.spawn(move || solver.work(pool, strategy, worker, seed));
+      }
+    });
+    self.finish_parallel(&puzzle, dictionary, options, pool, start)
+  }
+
+  /// Races a depth-first solve under each of `strategies`, on a thread each
+  /// and seeded differently, until one finds a fill or shows there is none,
+  /// or one reaches a limit of `options`. The stats add up every thread's.
+  pub fn solve_portfolio<R: Rng>(
+    &mut self,
+    dictionary: &Dictionary,
+    strategies: &[Strategy],
+    options: &SolveOptions,
+    rng: &mut R,
+  ) -> (Outcome, SolveStats) {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let solver = match self.prepare(&puzzle, dictionary) {
+      Some((solver, _)) => solver,
+      None => {
+        let failure = self.explain(&puzzle, dictionary, false, options);
+        return (Outcome::Failed(failure), SolveStats::default());
+      }
+    };
+    let pool = Pool::new(strategies.len(), vec![]);
+    let worker = worker_options(options);
+    let seeds: Vec<u64> = strategies.iter().map(|_| rng.gen()).collect();
+    let start = Instant::now();
+    thread::scope(|scope| {
+      for (&strategy, seed) in strategies.iter().zip(seeds) {
+        let mut solver = solver.clone();
+        let (pool, worker) = (&pool, &worker);
+        scope.spawn(move || {
+          let mut rng = StdRng::seed_from_u64(seed);
+          let mut observer = ();
+          let mut search = Search::new(strategy, worker, &mut rng, &mut observer);
+          search.stop = Some(pool.stop.clone());
+          let result = solver.run(&mut search, &mut vec![]);
+          pool.stats.lock().unwrap().add(&search.stats);
+          pool.end(result, search.stopped);
+        });
+      }
+    });
+    self.finish_parallel(&puzzle, dictionary, options, pool, start)
+  }
+
+  /// Fills the grid in from a parallel solve which began at `start` and
+  /// has finished. If a limit stopped it, the grid is left with no solved
+  /// squares.
+  fn finish_parallel(
+    &mut self,
+    puzzle: &Arc<Puzzle>,
+    dictionary: &Dictionary,
+    options: &SolveOptions,
+    pool: Pool,
+    start: Instant,
+  ) -> (Outcome, SolveStats) {
+    let mut stats = pool.stats.into_inner().unwrap();
+    stats.elapsed = start.elapsed();
+    self.clear_solved();
+    let outcome = match (pool.solution.into_inner().unwrap(), pool.limit.into_inner().unwrap()) {
+      (Some(chars), _) => {
+        self.write_solved(puzzle, &chars);
+        Outcome::Solved
+      }
+      (None, Some(limit)) => Outcome::Stopped(limit),
+      (None, None) => Outcome::Failed(self.explain(puzzle, dictionary, true, options)),
+    };
+    (outcome, stats)
+  }
+}
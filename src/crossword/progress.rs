@@ -0,0 +1,112 @@
+use super::{Choice, Search, Solver};
+use rand::Rng;
+use std::time::Duration;
+
+/// A snapshot of a running solve, as passed to an `Observer`.
+#[derive(Clone, Debug)]
+pub struct Progress {
+  // Letters chosen on the current search path.
+  pub depth: usize,
+  // Search nodes visited so far.
+  pub nodes: usize,
+  // Letter choices which failed and were undone so far.
+  pub backtracks: usize,
+  // Product of the remaining candidate counts of every line: a loose upper
+  // bound on the fills left below the current node.
+  pub remaining: f64,
+  // Roughly how long until a fill is found, if the observer asked for an
+  // estimate.
+  pub eta: Option<Duration>,
+}
+
+/// What a solve did to a choice.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepKind {
+  // Committed it, and propagation went through.
+  Commit,
+  // Committed it, and propagation hit a dead end, so it was undone.
+  Fail,
+  // Undid it after the search below it failed.
+  Backtrack,
+}
+
+impl StepKind {
+  fn name(self) -> &'static str {
+    match self {
+      StepKind::Commit => "commit",
+      StepKind::Fail => "fail",
+      StepKind::Backtrack => "backtrack",
+    }
+  }
+}
+
+/// One step of a running solve, as passed to an `Observer`.
+#[derive(Clone, Debug)]
+pub struct Step {
+  pub kind: StepKind,
+  pub depth: usize,
+  // The squares of the choice: the square a letter was chosen for, or every
+  // square of the entry a word was chosen for.
+  pub chosen: Vec<(usize, usize)>,
+  // Every square of the puzzle, with its letter once it's down to one, after
+  // the step.
+  pub letters: Vec<((usize, usize), Option<char>)>,
+}
+
+/// Receives progress reports from a solve, e.g. to show them in a UI or log.
+pub trait Observer {
+  fn progress(&mut self, progress: &Progress);
+
+  /// Whether the solve should estimate its ETA when it starts, which costs
+  /// some random probes down the search tree.
+  fn wants_eta(&self) -> bool {
+    false
+  }
+
+  /// Called after every commit and backtrack while `wants_steps` says so.
+  /// The solve waits for it to return, so it can pause the solve.
+  fn step(&mut self, _: &Step) {}
+
+  fn wants_steps(&self) -> bool {
+    false
+  }
+}
+
+/// Ignores every report.
+impl Observer for () {
+  fn progress(&mut self, _: &Progress) {}
+}
+
+impl Solver {
+  fn chosen_squares(&self, choice: Choice) -> Vec<(usize, usize)> {
+    let cells = match choice {
+      Choice::Letter(ci, _) => vec![ci],
+      Choice::Word(li, _) => {
+        let line = &self.puzzle.lines[li];
+        line.cell_indices.iter().map(|ci| *ci as usize).collect()
+      }
+    };
+    let positions = &self.puzzle.cell_positions;
+    cells.into_iter().map(|ci| positions[ci]).collect()
+  }
+}
+
+impl<R: Rng> Search<'_, R> {
+  /// Tells the trace and the observer what the solve did to `choice` at
+  /// `depth`.
+  pub(super) fn step(&mut self, solver: &Solver, kind: StepKind, depth: usize, choice: Choice) {
+    self.trace(solver, kind.name(), depth, choice);
+    if !self.observer.wants_steps() {
+      return;
+    }
+    let positions = &solver.puzzle.cell_positions;
+    self.observer.step(&Step {
+      kind,
+      depth,
+      chosen: solver.chosen_squares(choice),
+      letters: (0..positions.len())
+        .map(|ci| (positions[ci], solver.solved_char(ci)))
+        .collect(),
+    });
+  }
+}
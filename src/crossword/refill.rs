@@ -0,0 +1,310 @@
+use super::{
+  Branch, Cell, Grid, Levels, Limit, Line, Observer, Outcome, Puzzle, Search, SolveOptions,
+  SolveStats, Solver, Square, Strategy, VariableOrder, WordIndex,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use words::dictionary::Dictionary;
+use words::LetterSet;
+
+type Refilled = Option<(usize, Vec<(usize, char)>)>;
+
+fn beats(bound: usize, best: &Refilled) -> bool {
+  match best {
+    Some((changes, _)) => bound < *changes,
+    None => true,
+  }
+}
+
+impl Puzzle {
+  /// The part of the grid's puzzle made of the lines through any square of
+  /// `region`, and through any empty square of `grid` on those lines, so that
+  /// every square the search can fill keeps both of its entries. A filled
+  /// cell outside the region may only have one of its lines in it, in which
+  /// case that line fills both of the cell's line slots.
+  fn around(&self, grid: &Grid, region: &HashSet<(usize, usize)>) -> Puzzle {
+    let open = |ci: usize| {
+      let position = self.cell_positions[ci];
+      region.contains(&position) || matches!(grid.squares[&position], Square::Empty)
+    };
+    let mut kept_lines = vec![false; self.lines.len()];
+    let mut seen = vec![false; self.cells.len()];
+    let mut queue: Vec<usize> = (0..self.cells.len())
+      .filter(|ci| region.contains(&self.cell_positions[*ci]))
+      .collect();
+    for ci in &queue {
+      seen[*ci] = true;
+    }
+    while let Some(ci) = queue.pop() {
+      for (li, _) in &self.cells[ci].lines {
+        let li = *li as usize;
+        if kept_lines[li] {
+          continue;
+        }
+        kept_lines[li] = true;
+        for other in &self.lines[li].cell_indices {
+          let other = *other as usize;
+          if !seen[other] && open(other) {
+            seen[other] = true;
+            queue.push(other);
+          }
+        }
+      }
+    }
+    let lines: Vec<&Line> = (self.lines.iter().zip(kept_lines))
+      .filter(|(_, kept)| *kept)
+      .map(|(line, _)| line)
+      .collect();
+    let mut kept: Vec<u32> = lines
+      .iter()
+      .flat_map(|line| line.cell_indices.clone())
+      .collect();
+    // Still in reading order, so that a seeded solve visits cells
+    // deterministically.
+    kept.sort_unstable();
+    kept.dedup();
+    let renumbered: HashMap<u32, u32> = kept
+      .iter()
+      .enumerate()
+      .map(|(ci, whole_ci)| (*whole_ci, ci as u32))
+      .collect();
+    let lines: Vec<Line> = lines
+      .into_iter()
+      .map(|line| Line {
+        direction: line.direction,
+        cell_indices: line.cell_indices.iter().map(|ci| renumbered[ci]).collect(),
+      })
+      .collect();
+    let mut slots = vec![[None; 2]; kept.len()];
+    for (li, line) in lines.iter().enumerate() {
+      for (pos, ci) in line.cell_indices.iter().enumerate() {
+        slots[*ci as usize][line.direction as usize] = Some((li as u32, pos as u8));
+      }
+    }
+    Puzzle {
+      lines,
+      cells: slots
+        .into_iter()
+        .map(|[across, down]| Cell {
+          lines: [across.or(down).unwrap(), down.or(across).unwrap()],
+        })
+        .collect(),
+      cell_positions: kept
+        .iter()
+        .map(|ci| self.cell_positions[*ci as usize])
+        .collect(),
+    }
+  }
+}
+
+impl Solver {
+  /// Rules out `words` for every line, as entries elsewhere in the grid
+  /// already use them. Words of a length no line has are skipped. None if
+  /// that leaves no fill, including when a line is already settled to one of
+  /// them.
+  fn reserve(&mut self, words: &[String]) -> Option<()> {
+    let indexes: HashMap<usize, Arc<WordIndex>> = self
+      .line_states
+      .iter()
+      .map(|line| (line.words.length, line.words.clone()))
+      .collect();
+    for word in words {
+      let index = match indexes.get(&word.len()) {
+        Some(index) => index,
+        None => continue,
+      };
+      let ords: Option<Vec<u8>> = word.chars().map(LetterSet::index).collect();
+      if let Some(id) = ords.and_then(|ords| index.id(&ords)) {
+        // `narrowed` lets a settled line keep its word, so check those here.
+        if self.committed.contains_key(&(word.len(), id)) {
+          return None;
+        }
+        let used = self.used.entry(word.len()).or_default();
+        if let Err(at) = used.binary_search(&id) {
+          used.insert(at, id);
+        }
+      }
+    }
+    let mut dirty = vec![];
+    for li in 0..self.line_states.len() {
+      let candidates = self.narrowed(li, None);
+      self.narrow_line(li, candidates, &Levels::default(), &mut dirty)?;
+    }
+    self.propagate(dirty)?;
+    // Like the fixed squares, nothing here is ever undone.
+    self.trail.clear();
+    self.trail_bytes = 0;
+    Some(())
+  }
+
+  /// The cells whose letter in `previous` is ruled out, which any fill below
+  /// this state has to change. Once every cell is down to one letter, it is
+  /// exactly the cells the fill changes.
+  fn changes(&self, previous: &[Option<u8>]) -> usize {
+    let cells = previous.iter().enumerate();
+    cells
+      .filter(|(ci, o)| match o {
+        Some(o) => !self.cell_set(*ci).contains_index(*o),
+        None => false,
+      })
+      .count()
+  }
+
+  /// Depth-first branch and bound below the current state, trying each
+  /// cell's letter from `previous` first. Partial fills which have to change
+  /// as many cells as the best complete fill so far are pruned, which
+  /// `changes` tells early as it only grows going deeper. Leaves the best
+  /// fill found in `best`, and returns the limit which stopped the search,
+  /// if one did.
+  fn refill<R: Rng>(
+    &mut self,
+    previous: &[Option<u8>],
+    search: &mut Search<R>,
+    best: &mut Refilled,
+  ) -> Result<(), Limit> {
+    if let Some(limit) = search.limit_reached() {
+      return Err(limit);
+    }
+    search.nodes += 1;
+    let (ci, set) = match self.pick_cell(VariableOrder::Fewest) {
+      Branch::DeadEnd => return Ok(()),
+      Branch::Complete => {
+        *best = Some((self.changes(previous), self.solution()));
+        return Ok(());
+      }
+      Branch::At(at) => at,
+    };
+    let mut letters: Vec<u8> = set.indices().collect();
+    if let Some(i) = letters.iter().position(|o| Some(*o) == previous[ci]) {
+      letters[..=i].rotate_right(1);
+    }
+    for o in letters {
+      let mark = self.trail.len();
+      let result = match self.commit_ord(ci, o) {
+        Some(_) if beats(self.changes(previous), best) => self.refill(previous, search, best),
+        _ => Ok(()),
+      };
+      self.undo(mark);
+      result?;
+    }
+    Ok(())
+  }
+}
+
+impl Grid {
+  /// The words of the filled in entries which don't pass through `region`.
+  fn words_outside(&self, puzzle: &Puzzle, region: &HashSet<(usize, usize)>) -> Vec<String> {
+    let letter = |ci: &u32| {
+      let position = puzzle.cell_positions[*ci as usize];
+      match self.squares[&position] {
+        _ if region.contains(&position) => None,
+        Square::Fixed(ch) | Square::Solved(ch) => Some(ch),
+        Square::Empty => None,
+      }
+    };
+    let lines = puzzle.lines.iter();
+    lines
+      .filter_map(|line| line.cell_indices.iter().map(letter).collect())
+      .collect()
+  }
+
+  /// Fills the grid again, keeping as many of its solved letters as it can:
+  /// they're preferred rather than fixed, so the new fill is the valid one
+  /// which changes the fewest of them. If a limit of `options` stops the
+  /// search first, the outcome is `Stopped` and the fill is the best found
+  /// by then. Returns how many solved letters the new fill changes; with no
+  /// new fill, the grid is left as it was.
+  pub fn refill(
+    &mut self,
+    dictionary: &Dictionary,
+    options: &SolveOptions,
+  ) -> (Outcome, Option<usize>) {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let previous: Vec<_> = puzzle
+      .cell_positions
+      .iter()
+      .map(|position| match self.squares[position] {
+        Square::Solved(ch) => LetterSet::index(ch),
+        Square::Fixed(_) | Square::Empty => None,
+      })
+      .collect();
+    let mut best = None;
+    let prepared = self.prepare(&puzzle, dictionary);
+    let exhausted = prepared.is_some();
+    let result = match prepared {
+      Some((mut solver, _)) => {
+        // The search tries letters in a fixed order, so it has no use for
+        // randomness.
+        let (mut rng, mut observer) = (StdRng::seed_from_u64(0), ());
+        let mut search = Search::new(Strategy::default(), options, &mut rng, &mut observer);
+        solver.refill(&previous, &mut search, &mut best)
+      }
+      None => Ok(()),
+    };
+    let changes = best.map(|(changes, chars)| {
+      self.clear_solved();
+      self.write_solved(&puzzle, &chars);
+      changes
+    });
+    let outcome = match (result, changes) {
+      (Err(limit), _) => Outcome::Stopped(limit),
+      (Ok(()), Some(_)) => Outcome::Solved,
+      (Ok(()), None) => Outcome::Failed(self.explain(&puzzle, dictionary, exhausted, options)),
+    };
+    (outcome, changes)
+  }
+
+  /// Solves only the squares of `region`, depth-first as `solve` does. Every
+  /// square outside it stays as it is, and only the entries through the
+  /// region, or through an empty square crossing those, are searched, so the
+  /// cost grows with the region rather than the grid. The refill can't be
+  /// resumed, so a limit stops it rather than pausing it, and the grid is
+  /// left as it was unless the region is solved.
+  pub fn refill_region<R: Rng>(
+    &mut self,
+    dictionary: &Dictionary,
+    region: &HashSet<(usize, usize)>,
+    strategy: Strategy,
+    options: &SolveOptions,
+    rng: &mut R,
+    observer: &mut dyn Observer,
+  ) -> (Outcome, SolveStats) {
+    let mut frozen = self.clone();
+    for (position, square) in frozen.squares.iter_mut() {
+      if let Square::Solved(ch) = *square {
+        if !region.contains(position) {
+          *square = Square::Fixed(ch);
+        }
+      }
+    }
+    let whole = Puzzle::new(&frozen);
+    let puzzle = Arc::new(whole.around(&frozen, region));
+    let words = frozen.words_outside(&whole, region);
+    let prepared = frozen.prepare(&puzzle, dictionary);
+    let prepared = prepared.and_then(|(mut solver, _)| solver.reserve(&words).map(|_| solver));
+    let (outcome, stats) = match prepared {
+      Some(solver) => {
+        let search = Search::new(strategy, options, rng, observer);
+        frozen.run_search(&puzzle, dictionary, solver, vec![], search)
+      }
+      None => {
+        let failure = frozen.explain(&puzzle, dictionary, false, options);
+        (Outcome::Failed(failure), SolveStats::default())
+      }
+    };
+    let outcome = match outcome {
+      Outcome::Solved => {
+        for position in region {
+          if let Some(square) = frozen.squares.get(position) {
+            self.squares.insert(*position, square.clone());
+          }
+        }
+        Outcome::Solved
+      }
+      Outcome::Paused(_, limit) => Outcome::Stopped(limit),
+      outcome => outcome,
+    };
+    (outcome, stats)
+  }
+}
@@ -0,0 +1,113 @@
+use super::{
+  Closeness, Grid, Limit, Observer, Outcome, Puzzle, Search, SolveOptions, SolveResult,
+  SolveStats, Strategy,
+};
+use rand::Rng;
+use std::sync::Arc;
+use std::time::Instant;
+use words::dictionary::Dictionary;
+
+/// How many nodes each run of a restarting solve may visit before it starts
+/// again from the top with fresh random choices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Restarts {
+  // `unit` times the Luby sequence 1, 1, 2, 1, 1, 2, 4, 1, ...
+  Luby { unit: usize },
+  // `first` nodes, then `factor` times as many on each restart after.
+  Geometric { first: usize, factor: f64 },
+}
+
+impl Restarts {
+  /// The node limit for run `run`, counting from 0.
+  fn limit(&self, run: usize) -> usize {
+    match *self {
+      Restarts::Luby { unit } => unit.saturating_mul(luby(run + 1)),
+      Restarts::Geometric { first, factor } => {
+        (first as f64 * factor.max(1.0).powi(run as i32)).min(usize::MAX as f64) as usize
+      }
+    }
+  }
+}
+
+/// The `i`th term of the Luby sequence, counting from 1.
+fn luby(mut i: usize) -> usize {
+  loop {
+    let mut k = 1;
+    while (1 << k) - 1 < i {
+      k += 1;
+    }
+    if (1 << k) - 1 == i {
+      return 1 << (k - 1);
+    }
+    i -= (1 << (k - 1)) - 1;
+  }
+}
+
+impl Grid {
+  /// Solves the grid depth-first like `solve`, but starts the search over
+  /// whenever a run visits more nodes than `restarts` allows it, so a few
+  /// unlucky early choices can't stall the whole solve. Nogoods and failure
+  /// weights carry over between runs. Stops at the first complete fill; if
+  /// a limit of `options` stops it first, the closest partial fill any run
+  /// reached is filled in, by `options.keep_closest` or else by filled
+  /// squares, since the last run's alone says little. The last run can be
+  /// resumed. A failed solve leaves the grid as `solve` would.
+  pub fn solve_restarting<R: Rng>(
+    &mut self,
+    dictionary: &Dictionary,
+    strategy: Strategy,
+    restarts: Restarts,
+    options: &SolveOptions,
+    rng: &mut R,
+    observer: &mut dyn Observer,
+  ) -> (Outcome, SolveStats) {
+    let puzzle = Arc::new(Puzzle::new(self));
+    let mut solver = match self.prepare(&puzzle, dictionary) {
+      Some((solver, _)) => solver,
+      None => {
+        let failure = self.explain(&puzzle, dictionary, false, options);
+        return (Outcome::Failed(failure), SolveStats::default());
+      }
+    };
+    let keep_closest = options.keep_closest.is_some();
+    let mut options = options.clone();
+    options.keep_closest.get_or_insert(Closeness::FilledSquares);
+    let mut search = Search::new(strategy, &options, rng, observer);
+    self.clear_solved();
+    let start = Instant::now();
+    let mut run = 0;
+    let (result, stack) = loop {
+      search.max_nodes = search.nodes.saturating_add(restarts.limit(run).max(1));
+      let mut stack = vec![];
+      match solver.run(&mut search, &mut stack) {
+        SolveResult::Incomplete(_) if search.stopped.is_none() => {
+          solver.restart();
+          run += 1;
+        }
+        result => break (result, stack),
+      }
+    };
+    search.stats.elapsed = start.elapsed();
+    let outcome = match result {
+      SolveResult::Solution(chars) => {
+        self.write_solved(&puzzle, &chars);
+        Outcome::Solved
+      }
+      SolveResult::None => {
+        match &search.closest {
+          Some((_, closest)) if keep_closest => self.write_solved(&puzzle, closest),
+          _ => {}
+        }
+        Outcome::Failed(self.explain(&puzzle, dictionary, true, &options))
+      }
+      SolveResult::Incomplete(_) => {
+        if let Some((_, closest)) = &search.closest {
+          self.write_solved(&puzzle, closest);
+        }
+        let limit = search.stopped.unwrap_or(Limit::Nodes);
+        Outcome::Paused(self.paused(&puzzle, &search, &mut solver, stack), limit)
+      }
+    };
+    (outcome, search.stats)
+  }
+}
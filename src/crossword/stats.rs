@@ -0,0 +1,156 @@
+use super::{ord_char, Candidates, Choice, LineState, Search, Solver, Undo};
+use rand::Rng;
+use std::fmt;
+use std::io::Write;
+use std::mem::size_of;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use words::LetterSet;
+
+/// Counts and timings from one depth-first solve, or summed over the
+/// threads of a parallel one.
+#[derive(Clone, Debug, Default)]
+pub struct SolveStats {
+  pub nodes: usize,
+  // Choices undone at each depth, whether they failed as they were committed
+  // or the search backed out of them later.
+  pub backtracks: Vec<usize>,
+  // Choices committed, and the lines their propagation narrowed.
+  pub commits: usize,
+  pub narrowings: usize,
+  // Time spent committing choices and propagating them.
+  pub propagation_time: Duration,
+  // Most heap bytes of line states saved on the trail at once, which is
+  // what a solve's memory grows by as it goes deeper. It doesn't count the
+  // solver's current line states or the word indexes.
+  pub peak_trail_bytes: usize,
+  pub elapsed: Duration,
+}
+
+impl SolveStats {
+  /// Adds the counts of another solve's stats to these, as the threads of
+  /// a parallel solve each keep their own. The elapsed time is left alone.
+  pub(super) fn add(&mut self, other: &SolveStats) {
+    self.nodes += other.nodes;
+    if self.backtracks.len() < other.backtracks.len() {
+      self.backtracks.resize(other.backtracks.len(), 0);
+    }
+    for (total, count) in self.backtracks.iter_mut().zip(&other.backtracks) {
+      *total += count;
+    }
+    self.commits += other.commits;
+    self.narrowings += other.narrowings;
+    self.propagation_time += other.propagation_time;
+    self.peak_trail_bytes = self.peak_trail_bytes.max(other.peak_trail_bytes);
+  }
+}
+
+/// Where a solve writes its commit and backtrack events, one JSON object
+/// per line. Clones write to the same place. Write errors are ignored.
+#[derive(Clone)]
+pub struct Trace(Arc<Mutex<dyn Write + Send>>);
+
+impl Trace {
+  pub fn new<W: Write + Send + 'static>(out: W) -> Trace {
+    Trace(Arc::new(Mutex::new(out)))
+  }
+
+  /// Writes out whatever the trace has buffered.
+  pub fn flush(&self) {
+    let _ = self.0.lock().unwrap().flush();
+  }
+}
+
+impl fmt::Debug for Trace {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("Trace")
+  }
+}
+
+impl LineState {
+  /// Bytes of the heap the state holds on its own, not counting the shared
+  /// word index.
+  pub(super) fn heap_size(&self) -> usize {
+    let counts: usize = self.position_counts.iter().map(|c| c.len()).sum();
+    let candidates = match &self.candidates {
+      Candidates::Bits(bits) => bits.len() * size_of::<u64>(),
+      Candidates::Ids(ids) => ids.len() * size_of::<u32>(),
+    };
+    self.position_letters.len() * size_of::<LetterSet>()
+      + counts * size_of::<u32>()
+      + candidates
+      + self.reasons.0.len() * size_of::<u64>()
+  }
+}
+
+impl Solver {
+  /// The choice as JSON fields: the square and letter of a letter choice, or
+  /// the entry and word of a word choice.
+  fn describe(&self, choice: Choice) -> String {
+    let puzzle = &self.puzzle;
+    match choice {
+      Choice::Letter(ci, o) => {
+        let (x, y) = puzzle.cell_positions[ci];
+        format!("\"square\":[{},{}],\"letter\":\"{}\"", x, y, ord_char(o))
+      }
+      Choice::Word(li, id) => {
+        let line = &puzzle.lines[li];
+        let (x, y) = puzzle.cell_positions[line.cell_indices[0] as usize];
+        let word: String = self.line_states[li]
+          .words
+          .word(id)
+          .iter()
+          .map(|o| ord_char(*o))
+          .collect();
+        format!(
+          "\"entry\":[{},{},\"{}\"],\"word\":\"{}\"",
+          x,
+          y,
+          if line.direction == 0 {
+            "across"
+          } else {
+            "down"
+          },
+          word
+        )
+      }
+    }
+  }
+}
+
+impl<R: Rng> Search<'_, R> {
+  /// Counts a commit which began at `start`, with the changes it made on the
+  /// trail from `mark`.
+  pub(super) fn committed(&mut self, solver: &Solver, mark: usize, start: Instant) {
+    self.stats.propagation_time += start.elapsed();
+    self.stats.commits += 1;
+    let changes = solver.trail[mark..].iter();
+    let narrowed = changes
+      .filter(|undo| matches!(undo, Undo::Line(..)))
+      .count();
+    self.stats.narrowings += narrowed;
+    self.stats.peak_trail_bytes = self.stats.peak_trail_bytes.max(solver.trail_bytes);
+  }
+
+  pub(super) fn backtracked(&mut self, depth: usize) {
+    self.backtracks += 1;
+    if self.stats.backtracks.len() <= depth {
+      self.stats.backtracks.resize(depth + 1, 0);
+    }
+    self.stats.backtracks[depth] += 1;
+  }
+
+  /// Writes an event about `choice` at `depth` to the trace, if there is one.
+  pub(super) fn trace(&self, solver: &Solver, event: &str, depth: usize, choice: Choice) {
+    if let Some(Trace(out)) = &self.options.trace {
+      let _ = writeln!(
+        out.lock().unwrap(),
+        "{{\"event\":\"{}\",\"node\":{},\"depth\":{},{}}}",
+        event,
+        self.nodes,
+        depth,
+        solver.describe(choice)
+      );
+    }
+  }
+}